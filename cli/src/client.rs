@@ -0,0 +1,481 @@
+use crate::{http2, tls};
+use parser::{parse_authority, Method, Target, TargetError, TargetForm};
+use rustls::pki_types::CertificateDer;
+use rustls::{ClientConnection, StreamOwned};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum ClientError {
+    InvalidUrl(String),
+    Io(io::Error),
+    InvalidResponse(String),
+    Unsupported(String),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::InvalidUrl(x) => write!(f, "invalid url: {}", x),
+            ClientError::Io(e) => write!(f, "{}", e),
+            ClientError::InvalidResponse(x) => write!(f, "invalid response: {}", x),
+            ClientError::Unsupported(x) => write!(f, "unsupported: {}", x),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<io::Error> for ClientError {
+    fn from(e: io::Error) -> Self {
+        ClientError::Io(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    /// timeout for reading the response
+    pub timeout: Option<Duration>,
+    pub connection_timeout: Option<Duration>,
+    /// maximum number of redirects to follow, 0 disables redirects
+    pub max_redirects: usize,
+    /// root certificates to trust in addition to the webpki roots
+    pub roots: Vec<CertificateDer<'static>>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            timeout: None,
+            connection_timeout: None,
+            max_redirects: 10,
+            roots: vec![],
+        }
+    }
+}
+
+/// A request with all placeholders and file references resolved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpRequest {
+    pub method: String,
+    /// absolute url or origin-form target with a Host header
+    pub target: Target,
+    /// e.g. "HTTP/1.1" or "HTTP/2"
+    pub version: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Body,
+}
+
+/// Request body, files are not read until the request is sent.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Body {
+    chunks: Vec<Chunk>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Chunk {
+    Bytes(Vec<u8>),
+    File(PathBuf),
+}
+
+impl Body {
+    pub fn push_bytes<B: Into<Vec<u8>>>(&mut self, bytes: B) {
+        let bytes = bytes.into();
+        match self.chunks.last_mut() {
+            _ if bytes.is_empty() => {}
+            Some(Chunk::Bytes(x)) => x.extend(bytes),
+            _ => self.chunks.push(Chunk::Bytes(bytes)),
+        }
+    }
+
+    pub fn push_file(&mut self, path: PathBuf) {
+        self.chunks.push(Chunk::File(path));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    // length in bytes, sizes of the files are read from their metadata
+    pub fn len(&self) -> io::Result<u64> {
+        let mut len = 0;
+        for chunk in self.chunks.iter() {
+            len += match chunk {
+                Chunk::Bytes(x) => x.len() as u64,
+                Chunk::File(path) => fs::metadata(path).map_err(|e| file_error(path, e))?.len(),
+            };
+        }
+        Ok(len)
+    }
+
+    // reader of the chunks in sequence, all files are opened first
+    pub fn reader(&self) -> io::Result<Box<dyn Read + '_>> {
+        let mut reader: Box<dyn Read + '_> = Box::new(io::empty());
+        for chunk in self.chunks.iter() {
+            reader = match chunk {
+                Chunk::Bytes(x) => Box::new(reader.chain(x.as_slice())),
+                Chunk::File(path) => {
+                    let file = File::open(path).map_err(|e| file_error(path, e))?;
+                    Box::new(reader.chain(file))
+                }
+            };
+        }
+        Ok(reader)
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Self {
+        let mut body = Body::default();
+        body.push_bytes(bytes);
+        body
+    }
+}
+
+fn file_error(path: &Path, e: io::Error) -> io::Error {
+    io::Error::new(e.kind(), format!("{}: {}", path.display(), e))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpResponse {
+    pub version: String,
+    pub status: u16,
+    pub reason: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+}
+
+impl HttpResponse {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+}
+
+fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+// host, port and origin-form path of the request
+#[derive(Debug, PartialEq)]
+pub(crate) struct Destination {
+    pub tls: bool,
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl Destination {
+    pub fn scheme(&self) -> &'static str {
+        if self.tls {
+            "https"
+        } else {
+            "http"
+        }
+    }
+
+    // host with the port if it is not the default one of the scheme
+    pub fn authority(&self) -> String {
+        match (self.tls, self.port) {
+            (false, 80) | (true, 443) => self.host.clone(),
+            _ => format!("{}:{}", self.host, self.port),
+        }
+    }
+}
+
+fn destination(req: &HttpRequest) -> Result<Destination, ClientError> {
+    let invalid = |e: TargetError| ClientError::InvalidUrl(e.to_string());
+    let target = &req.target;
+
+    let tls = target.scheme.as_deref() == Some("https");
+    let (host, port) = match target.form {
+        TargetForm::Absolute => match target.scheme.as_deref() {
+            None | Some("http") | Some("https") => (target.host.clone(), target.port),
+            Some(x) => {
+                return Err(ClientError::InvalidUrl(format!(
+                    "unsupported scheme: '{}'",
+                    x
+                )))
+            }
+        },
+        TargetForm::Authority => (target.host.clone(), target.port),
+        TargetForm::Origin | TargetForm::Asterisk => match req.header("Host") {
+            Some(host) => parse_authority(host).map_err(invalid)?,
+            None => {
+                return Err(ClientError::InvalidUrl(format!(
+                    "'{}' has no host and there is no Host header",
+                    req.target
+                )))
+            }
+        },
+    };
+
+    let path = match target.form {
+        TargetForm::Authority => req.target.to_string(),
+        _ => target.path_and_query(),
+    };
+
+    Ok(Destination {
+        tls,
+        // host is always set if parsing succeeds
+        host: host.unwrap_or_default(),
+        port: port.unwrap_or(if tls { 443 } else { 80 }),
+        path,
+    })
+}
+
+// send the request and follow redirects
+pub fn execute(req: &HttpRequest, opts: &Options) -> Result<HttpResponse, ClientError> {
+    let mut req = req.clone();
+    let mut res = send(&req, opts)?;
+
+    for _ in 0..opts.max_redirects {
+        let location = match (res.status, res.header("Location")) {
+            (301 | 302 | 303 | 307 | 308, Some(x)) => x.to_string(),
+            _ => break,
+        };
+
+        req = redirect(&req, res.status, &location)?;
+        res = send(&req, opts)?;
+    }
+
+    Ok(res)
+}
+
+// request to send for the redirect response
+fn redirect(req: &HttpRequest, status: u16, location: &str) -> Result<HttpRequest, ClientError> {
+    let dest = destination(req)?;
+    let origin = format!("{}://{}:{}", dest.scheme(), dest.host, dest.port);
+
+    let target = if location.contains("://") {
+        location.to_string()
+    } else if location.starts_with('/') {
+        format!("{}{}", origin, location)
+    } else {
+        // relative to the directory of the current path
+        let path = dest.path.split('?').next().unwrap_or_default();
+        let dir = &path[..path.rfind('/').map(|x| x + 1).unwrap_or(0)];
+        format!("{}{}{}", origin, dir, location)
+    };
+
+    let mut next = req.clone();
+    next.target = Target::parse(&Method::from(req.method.as_str()), &target)
+        .map_err(|e| ClientError::InvalidUrl(e.to_string()))?;
+    // Host header belongs to the previous destination
+    next.headers
+        .retain(|(k, _)| !k.eq_ignore_ascii_case("Host"));
+
+    // credentials are only sent to the origin they are written for
+    let next_dest = destination(&next)?;
+    if (next_dest.scheme(), &next_dest.host, next_dest.port)
+        != (dest.scheme(), &dest.host, dest.port)
+    {
+        next.headers.retain(|(k, _)| {
+            !["Authorization", "Cookie", "Proxy-Authorization"]
+                .iter()
+                .any(|x| k.eq_ignore_ascii_case(x))
+        });
+    }
+
+    if status == 303 || ((status == 301 || status == 302) && req.method == "POST") {
+        next.method = "GET".to_string();
+        next.body = Body::default();
+        next.headers.retain(|(k, _)| {
+            !k.eq_ignore_ascii_case("Content-Length") && !k.eq_ignore_ascii_case("Content-Type")
+        });
+    }
+
+    Ok(next)
+}
+
+pub(crate) fn connect(dest: &Destination, timeout: Option<Duration>) -> io::Result<TcpStream> {
+    let timeout = match timeout {
+        Some(x) => x,
+        None => return TcpStream::connect((dest.host.as_str(), dest.port)),
+    };
+
+    let mut last_err = io::Error::new(io::ErrorKind::NotFound, "host not found");
+    for addr in (dest.host.as_str(), dest.port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_err = e,
+        }
+    }
+
+    Err(last_err)
+}
+
+// send the request and read the response. connection is closed after each request.
+pub fn send(req: &HttpRequest, opts: &Options) -> Result<HttpResponse, ClientError> {
+    let dest = destination(req)?;
+
+    match req.version.as_str() {
+        "HTTP/2" => http2::send(req, &dest, opts),
+        "HTTP/3" => Err(ClientError::Unsupported("HTTP/3".to_string())),
+        _ => send_http1(req, &dest, opts),
+    }
+}
+
+pub(crate) fn send_http1(
+    req: &HttpRequest,
+    dest: &Destination,
+    opts: &Options,
+) -> Result<HttpResponse, ClientError> {
+    let stream = connect(dest, opts.connection_timeout)?;
+    stream.set_read_timeout(opts.timeout)?;
+
+    if !dest.tls {
+        return exchange(stream, req, dest);
+    }
+
+    let config = tls::config(&[tls::ALPN_HTTP1], &opts.roots)?;
+    let conn =
+        ClientConnection::new(config, tls::server_name(&dest.host)?).map_err(io::Error::other)?;
+    exchange(StreamOwned::new(conn, stream), req, dest)
+}
+
+fn exchange<S: Read + Write>(
+    mut stream: S,
+    req: &HttpRequest,
+    dest: &Destination,
+) -> Result<HttpResponse, ClientError> {
+    write_request(&mut stream, req, dest)?;
+
+    let mut reader = BufReader::new(stream);
+    read_response(&mut reader, req.method == "HEAD")
+}
+
+fn write_request<W: Write>(w: &mut W, req: &HttpRequest, dest: &Destination) -> io::Result<()> {
+    let mut head = format!("{} {} {}\r\n", req.method, dest.path, req.version);
+
+    if req.header("Host").is_none() {
+        head.push_str(&format!("Host: {}\r\n", dest.authority()));
+    }
+    for (name, value) in req.headers.iter() {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    if !req.body.is_empty() && req.header("Content-Length").is_none() {
+        head.push_str(&format!("Content-Length: {}\r\n", req.body.len()?));
+    }
+    if req.header("Connection").is_none() {
+        head.push_str("Connection: close\r\n");
+    }
+    head.push_str("\r\n");
+
+    let mut body = req.body.reader()?;
+    w.write_all(head.as_bytes())?;
+    io::copy(&mut body, w)?;
+    w.flush()
+}
+
+fn read_response<R: BufRead>(r: &mut R, head_request: bool) -> Result<HttpResponse, ClientError> {
+    let status_line = read_line(r)?;
+    let mut parts = status_line.splitn(3, ' ');
+    let version = parts.next().unwrap_or_default().to_string();
+    let status: u16 = parts
+        .next()
+        .and_then(|x| x.parse().ok())
+        .ok_or_else(|| ClientError::InvalidResponse(status_line.clone()))?;
+    let reason = parts.next().unwrap_or_default().to_string();
+
+    let mut headers = vec![];
+    loop {
+        let line = read_line(r)?;
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| ClientError::InvalidResponse(line.clone()))?;
+        headers.push((name.to_string(), value.trim().to_string()));
+    }
+
+    let mut response = HttpResponse {
+        version,
+        status,
+        reason,
+        headers,
+        body: vec![],
+    };
+
+    let has_body = !head_request && !(100..200).contains(&status) && status != 204 && status != 304;
+    if !has_body {
+        return Ok(response);
+    }
+
+    let chunked = response
+        .header("Transfer-Encoding")
+        .map(|x| x.to_ascii_lowercase().contains("chunked"))
+        .unwrap_or(false);
+
+    if chunked {
+        response.body = read_chunked(r)?;
+    } else if let Some(len) = response.header("Content-Length") {
+        let len: usize = len.parse().map_err(|_| {
+            ClientError::InvalidResponse(format!("invalid content length: {}", len))
+        })?;
+        response.body = vec![0; len];
+        r.read_exact(&mut response.body)?;
+    } else {
+        match r.read_to_end(&mut response.body) {
+            // servers often close TLS connections without close_notify
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {}
+            x => {
+                x?;
+            }
+        }
+    }
+
+    Ok(response)
+}
+
+fn read_chunked<R: BufRead>(r: &mut R) -> Result<Vec<u8>, ClientError> {
+    let mut body = vec![];
+    loop {
+        let line = read_line(r)?;
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16)
+            .map_err(|_| ClientError::InvalidResponse(format!("invalid chunk size: {}", line)))?;
+        if size == 0 {
+            break;
+        }
+
+        let start = body.len();
+        body.resize(start + size, 0);
+        r.read_exact(&mut body[start..])?;
+        read_line(r)?; // CRLF after chunk data
+    }
+
+    // trailer fields are ignored
+    while let Ok(line) = read_line(r) {
+        if line.is_empty() {
+            break;
+        }
+    }
+
+    Ok(body)
+}
+
+// read a line without the line ending
+fn read_line<R: BufRead>(r: &mut R) -> Result<String, ClientError> {
+    let mut line = String::new();
+    if r.read_line(&mut line)? == 0 {
+        return Err(ClientError::InvalidResponse(
+            "connection closed unexpectedly".to_string(),
+        ));
+    }
+
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
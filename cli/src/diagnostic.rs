@@ -0,0 +1,30 @@
+use parser::ParseError;
+
+// render a parse error like rustc does:
+//
+// error: expected request line
+//  --> api.http:3:1
+//   |
+// 3 | {foo}
+//   | ^
+pub fn render(e: &ParseError) -> String {
+    let line_no = e.line.to_string();
+    let gutter = " ".repeat(line_no.len());
+
+    // keep tabs in the caret line so it stays aligned with the snippet
+    let padding: String = e
+        .snippet
+        .chars()
+        .take(e.column.saturating_sub(1))
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+
+    format!(
+        "error: {msg}\n{gutter}--> {file}:{line}:{col}\n{gutter} |\n{line} | {snippet}\n{gutter} | {padding}^\n",
+        msg = e.message,
+        file = e.filename,
+        line = line_no,
+        col = e.column,
+        snippet = e.snippet,
+    )
+}
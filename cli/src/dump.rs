@@ -0,0 +1,17 @@
+use clap::ValueEnum;
+use parser::owned::HttpFile;
+
+/// Output format of the `parse` command.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Format {
+    Json,
+    Yaml,
+}
+
+// parsed file with the source positions of the nodes
+pub fn dump(file: &HttpFile, format: Format) -> Result<String, String> {
+    match format {
+        Format::Json => serde_json::to_string_pretty(file).map_err(|e| e.to_string()),
+        Format::Yaml => serde_yaml::to_string(file).map_err(|e| e.to_string()),
+    }
+}
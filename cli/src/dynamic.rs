@@ -0,0 +1,164 @@
+use std::cell::Cell;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const ALPHABETIC: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const ALPHANUMERIC: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+const HEXADECIMAL: &[u8] = b"0123456789abcdef";
+
+/// Built-in `{{$name}}` variables. Every placeholder gets a new value.
+///
+/// Random values come from a splitmix64 generator, so runs with the same
+/// seed generate the same values. Timestamps of a seeded run are fixed to a
+/// time derived from the seed for the same reason.
+#[derive(Debug)]
+pub struct Dynamic {
+    state: Cell<u64>,
+    /// time since the epoch to use instead of the current time
+    clock: Option<Duration>,
+}
+
+// seeded clocks stay before 2100-01-01
+const MAX_CLOCK_SECS: u64 = 4_102_444_800;
+
+impl Default for Dynamic {
+    fn default() -> Self {
+        Dynamic {
+            state: Cell::new(now().as_nanos() as u64 ^ u64::from(std::process::id())),
+            clock: None,
+        }
+    }
+}
+
+impl Dynamic {
+    pub fn with_seed(seed: u64) -> Self {
+        Dynamic {
+            state: Cell::new(seed),
+            clock: Some(Duration::from_secs(seed % MAX_CLOCK_SECS)),
+        }
+    }
+
+    // value of `$name` or `$name(args)`, None if it is not a built-in variable
+    pub fn get(&self, name: &str) -> Option<String> {
+        let (name, args) = match name.split_once('(') {
+            Some((name, args)) => (name, args.strip_suffix(')')?),
+            None => (name, ""),
+        };
+        let args: Vec<&str> = args
+            .split(',')
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+            .collect();
+
+        match (name, args.as_slice()) {
+            ("$uuid" | "$random.uuid", []) => Some(self.uuid()),
+            ("$timestamp", []) => Some(self.now().as_secs().to_string()),
+            ("$isoTimestamp", []) => Some(iso_timestamp(self.now().as_millis() as u64)),
+            ("$randomInt", []) => Some(self.integer(0, 1000).to_string()),
+            ("$random.integer", []) => Some(self.integer(0, 1000).to_string()),
+            ("$random.integer", [from, to]) => {
+                let (from, to) = (from.parse().ok()?, to.parse().ok()?);
+                (from < to).then(|| self.integer(from, to).to_string())
+            }
+            ("$random.float", []) => Some(self.float(0.0, 1000.0).to_string()),
+            ("$random.float", [from, to]) => {
+                let (from, to): (f64, f64) = (from.parse().ok()?, to.parse().ok()?);
+                (from < to).then(|| self.float(from, to).to_string())
+            }
+            ("$random.alphabetic", [len]) => Some(self.string(ALPHABETIC, len.parse().ok()?)),
+            ("$random.alphanumeric", [len]) => Some(self.string(ALPHANUMERIC, len.parse().ok()?)),
+            ("$random.hexadecimal", [len]) => Some(self.string(HEXADECIMAL, len.parse().ok()?)),
+            ("$random.email", []) => Some(format!(
+                "{}@{}.com",
+                self.string(ALPHANUMERIC, 10),
+                self.string(ALPHABETIC, 8).to_ascii_lowercase()
+            )),
+            _ => None,
+        }
+    }
+
+    fn now(&self) -> Duration {
+        self.clock.unwrap_or_else(now)
+    }
+
+    fn next(&self) -> u64 {
+        let state = self.state.get().wrapping_add(0x9e3779b97f4a7c15);
+        self.state.set(state);
+
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    // in [from, to)
+    fn integer(&self, from: i64, to: i64) -> i64 {
+        let range = to.wrapping_sub(from) as u64;
+        from.wrapping_add((self.next() % range) as i64)
+    }
+
+    // in [from, to)
+    fn float(&self, from: f64, to: f64) -> f64 {
+        let unit = (self.next() >> 11) as f64 / (1u64 << 53) as f64;
+        from + unit * (to - from)
+    }
+
+    fn string(&self, chars: &[u8], len: usize) -> String {
+        (0..len)
+            .map(|_| chars[(self.next() % chars.len() as u64) as usize] as char)
+            .collect()
+    }
+
+    // random version 4 uuid
+    fn uuid(&self) -> String {
+        let mut bytes = [0u8; 16];
+        bytes[..8].copy_from_slice(&self.next().to_be_bytes());
+        bytes[8..].copy_from_slice(&self.next().to_be_bytes());
+        bytes[6] = (bytes[6] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+        let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        format!(
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        )
+    }
+}
+
+fn now() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+// e.g. 2023-03-12T10:10:10.200Z
+pub fn iso_timestamp(millis: u64) -> String {
+    let secs = millis / 1000;
+    let (days, rem) = (secs / 86400, secs % 86400);
+
+    // civil date from days since the epoch, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60,
+        millis % 1000
+    )
+}
@@ -0,0 +1,130 @@
+use crate::dynamic::Dynamic;
+use parser::{Template, VariableDeclaration, PRIVATE_ENV_FILE, PUBLIC_ENV_FILE};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum EnvError {
+    Io(PathBuf, io::Error),
+    Json(PathBuf, serde_json::Error),
+    NotFound(String),
+}
+
+impl fmt::Display for EnvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvError::Io(path, e) => write!(f, "error reading '{}': {}", path.display(), e),
+            EnvError::Json(path, e) => write!(f, "invalid env file '{}': {}", path.display(), e),
+            EnvError::NotFound(name) => write!(
+                f,
+                "environment '{}' not found in {} or {}",
+                name, PUBLIC_ENV_FILE, PRIVATE_ENV_FILE
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EnvError {}
+
+/// Variables of one environment, merged from the public and private env files.
+#[derive(Debug, Default)]
+pub struct Environment {
+    variables: HashMap<String, String>,
+    /// `@name = value` declarations of the .http file
+    pub file_variables: HashMap<String, String>,
+    /// variables set by response handlers with `client.global.set`
+    pub globals: HashMap<String, String>,
+    /// variables set by the pre-request script of the current request
+    pub request_variables: HashMap<String, String>,
+    /// `{{$uuid}}` like built-in variables
+    pub dynamic: Dynamic,
+}
+
+impl Environment {
+    // load environment `name` from the env files next to `http_file`.
+    // `http_file` is the filename carried in the extra of the parsed spans.
+    // values of the private file override the public ones.
+    pub fn load(http_file: &str, name: &str) -> Result<Self, EnvError> {
+        let dir = Path::new(http_file)
+            .parent()
+            .unwrap_or_else(|| Path::new(""));
+
+        let mut found = false;
+        let mut variables = HashMap::new();
+        for file in [PUBLIC_ENV_FILE, PRIVATE_ENV_FILE] {
+            if let Some(env) = read_env_file(&dir.join(file), name)? {
+                found = true;
+                variables.extend(env);
+            }
+        }
+
+        if !found {
+            return Err(EnvError::NotFound(name.to_string()));
+        }
+
+        Ok(Environment {
+            variables,
+            file_variables: HashMap::new(),
+            globals: HashMap::new(),
+            request_variables: HashMap::new(),
+            dynamic: Dynamic::default(),
+        })
+    }
+
+    // request variables override globals, globals override the file
+    // declarations and the declarations override the env files
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.request_variables
+            .get(name)
+            .or_else(|| self.globals.get(name))
+            .or_else(|| self.file_variables.get(name))
+            .or_else(|| self.variables.get(name))
+            .map(String::as_str)
+    }
+
+    // add the declaration, its value can use the ones declared before
+    pub fn declare(&mut self, v: &VariableDeclaration) {
+        let value = self.resolve(&v.value);
+        self.file_variables
+            .insert(v.name.fragment().to_string(), value);
+    }
+
+    // replace placeholders of the template with environment values
+    pub fn resolve(&self, t: &Template) -> String {
+        t.render(|name| {
+            if name.starts_with('$') {
+                self.dynamic.get(name)
+            } else {
+                self.get(name).map(String::from)
+            }
+        })
+    }
+}
+
+// returns None if file does not exist or does not define the environment
+fn read_env_file(path: &Path, name: &str) -> Result<Option<HashMap<String, String>>, EnvError> {
+    let content = match fs::read_to_string(path) {
+        Ok(x) => x,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(EnvError::Io(path.to_path_buf(), e)),
+    };
+
+    let envs: HashMap<String, HashMap<String, Value>> =
+        serde_json::from_str(&content).map_err(|e| EnvError::Json(path.to_path_buf(), e))?;
+
+    Ok(envs.get(name).map(|env| {
+        env.iter()
+            .filter_map(|(k, v)| match v {
+                Value::String(s) => Some((k.clone(), s.clone())),
+                Value::Number(n) => Some((k.clone(), n.to_string())),
+                Value::Bool(b) => Some((k.clone(), b.to_string())),
+                // objects are client settings like SSLConfiguration, not variables
+                _ => None,
+            })
+            .collect()
+    }))
+}
@@ -0,0 +1,194 @@
+use crate::client::{self, ClientError, Destination, HttpRequest, HttpResponse, Options};
+use crate::tls;
+use bytes::Bytes;
+use h2::SendStream;
+use std::future::{poll_fn, Future};
+use std::io::{self, Read};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+
+// headers that are only meaningful for HTTP/1.x connections
+const CONNECTION_HEADERS: [&str; 6] = [
+    "host",
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+// send the request over HTTP/2. plain connections use prior knowledge, TLS
+// connections negotiate h2 with ALPN and fall back to HTTP/1.1 otherwise.
+pub fn send(
+    req: &HttpRequest,
+    dest: &Destination,
+    opts: &Options,
+) -> Result<HttpResponse, ClientError> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_io()
+        .enable_time()
+        .build()?;
+
+    let res = runtime.block_on(async {
+        let stream = with_timeout(
+            opts.connection_timeout,
+            TcpStream::connect((dest.host.as_str(), dest.port)),
+        )
+        .await??;
+
+        if !dest.tls {
+            return with_timeout(opts.timeout, exchange(stream, req, dest))
+                .await?
+                .map(Some);
+        }
+
+        let config = tls::config(&[tls::ALPN_H2, tls::ALPN_HTTP1], &opts.roots)?;
+        let stream = with_timeout(
+            opts.connection_timeout,
+            TlsConnector::from(config).connect(tls::server_name(&dest.host)?, stream),
+        )
+        .await??;
+
+        if stream.get_ref().1.alpn_protocol() != Some(tls::ALPN_H2) {
+            return Ok(None);
+        }
+
+        with_timeout(opts.timeout, exchange(stream, req, dest))
+            .await?
+            .map(Some)
+    })?;
+
+    match res {
+        Some(x) => Ok(x),
+        None => {
+            let mut req = req.clone();
+            req.version = "HTTP/1.1".to_string();
+            client::send_http1(&req, dest, opts)
+        }
+    }
+}
+
+async fn with_timeout<F: Future>(timeout: Option<Duration>, f: F) -> io::Result<F::Output> {
+    match timeout {
+        Some(x) => tokio::time::timeout(x, f)
+            .await
+            .map_err(|e| io::Error::new(io::ErrorKind::TimedOut, e)),
+        None => Ok(f.await),
+    }
+}
+
+async fn exchange<T>(
+    io: T,
+    req: &HttpRequest,
+    dest: &Destination,
+) -> Result<HttpResponse, ClientError>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (client, connection) = h2::client::handshake(io).await.map_err(h2_error)?;
+    tokio::spawn(async move {
+        let _ = connection.await;
+    });
+
+    let request = build(req, dest)?;
+    let mut client = client.ready().await.map_err(h2_error)?;
+    let (response, mut stream) = client
+        .send_request(request, req.body.is_empty())
+        .map_err(h2_error)?;
+    if !req.body.is_empty() {
+        send_body(&mut stream, req).await?;
+    }
+
+    let (parts, mut body) = response.await.map_err(h2_error)?.into_parts();
+
+    let mut data = vec![];
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(h2_error)?;
+        let _ = body.flow_control().release_capacity(chunk.len());
+        data.extend_from_slice(&chunk);
+    }
+
+    Ok(HttpResponse {
+        version: "HTTP/2".to_string(),
+        status: parts.status.as_u16(),
+        reason: parts
+            .status
+            .canonical_reason()
+            .unwrap_or_default()
+            .to_string(),
+        headers: parts
+            .headers
+            .iter()
+            .map(|(k, v)| {
+                let value = String::from_utf8_lossy(v.as_bytes()).to_string();
+                (k.to_string(), value)
+            })
+            .collect(),
+        body: data,
+    })
+}
+
+// send the body in chunks as the flow control window allows, it is not
+// buffered in memory as a whole
+async fn send_body(stream: &mut SendStream<Bytes>, req: &HttpRequest) -> Result<(), ClientError> {
+    let mut body = req.body.reader()?;
+    let mut buf = vec![0; 16 * 1024];
+    loop {
+        let n = body.read(&mut buf)?;
+        if n == 0 {
+            return stream.send_data(Bytes::new(), true).map_err(h2_error);
+        }
+
+        let mut chunk = Bytes::copy_from_slice(&buf[..n]);
+        while !chunk.is_empty() {
+            stream.reserve_capacity(chunk.len());
+            let capacity = match poll_fn(|cx| stream.poll_capacity(cx)).await {
+                Some(x) => x.map_err(h2_error)?,
+                None => return Err(ClientError::InvalidResponse("stream closed".to_string())),
+            };
+            let data = chunk.split_to(capacity.min(chunk.len()));
+            stream.send_data(data, false).map_err(h2_error)?;
+        }
+    }
+}
+
+fn build(req: &HttpRequest, dest: &Destination) -> Result<http::Request<()>, ClientError> {
+    // Host header becomes the :authority pseudo header
+    let authority = match req.header("Host") {
+        Some(x) => x.to_string(),
+        None => dest.authority(),
+    };
+    let uri = format!("{}://{}{}", dest.scheme(), authority, dest.path);
+
+    let mut builder = http::Request::builder()
+        .method(req.method.as_str())
+        .uri(uri)
+        .version(http::Version::HTTP_2);
+    for (name, value) in req.headers.iter() {
+        let name = name.to_ascii_lowercase();
+        if CONNECTION_HEADERS.contains(&name.as_str()) {
+            continue;
+        }
+        if name == "te" && !value.eq_ignore_ascii_case("trailers") {
+            continue;
+        }
+        builder = builder.header(name, value);
+    }
+
+    builder
+        .body(())
+        .map_err(|e| ClientError::InvalidUrl(e.to_string()))
+}
+
+fn h2_error(e: h2::Error) -> ClientError {
+    if e.is_io() {
+        match e.into_io() {
+            Some(e) => ClientError::Io(e),
+            None => ClientError::InvalidResponse("connection error".to_string()),
+        }
+    } else {
+        ClientError::InvalidResponse(e.to_string())
+    }
+}
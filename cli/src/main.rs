@@ -1,11 +1,122 @@
-use parser;
-use std::env;
+mod client;
+mod diagnostic;
+mod dump;
+mod dynamic;
+mod environment;
+mod http2;
+mod runner;
+mod script;
+mod select;
+#[cfg(test)]
+mod tests;
+mod tls;
+
+use clap::{Args, Parser, Subcommand};
+use environment::Environment;
+use parser::{Item, Request, RequestInfo};
+use select::Selector;
 use std::fs::File;
-use std::io::Read;
+use std::io::{self, Read, Write};
+use std::process;
+
+#[derive(Parser)]
+#[command(name = "restman", about = "Parse and run requests of .http files")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print parsed requests
+    Parse(ParseArgs),
+    /// Send requests and print responses
+    Run(FileArgs),
+    /// Rewrite .http files in the canonical format
+    Fmt(FmtArgs),
+}
+
+#[derive(Args)]
+struct FmtArgs {
+    /// .http files
+    #[arg(required = true)]
+    files: Vec<String>,
+
+    /// only report the files that are not formatted, exit with 1 if any
+    #[arg(long)]
+    check: bool,
+}
+
+#[derive(Args)]
+struct ParseArgs {
+    #[command(flatten)]
+    select: SelectArgs,
+
+    /// output format
+    #[arg(long, value_enum, default_value = "json")]
+    format: dump::Format,
+}
+
+#[derive(Args)]
+struct FileArgs {
+    #[command(flatten)]
+    select: SelectArgs,
+
+    /// environment to use from http-client.env.json and http-client.private.env.json
+    #[arg(long)]
+    env: Option<String>,
+
+    /// compare responses with the files of their `<> path` lines
+    #[arg(long)]
+    diff: bool,
+
+    /// seed of the random `{{$uuid}}` like variables to reproduce a run,
+    /// `{{$timestamp}}` is fixed to a time derived from the seed
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+#[derive(Args)]
+struct SelectArgs {
+    /// .http file
+    file: String,
+
+    /// only the request with the given `# @name` or title
+    #[arg(long, group = "selector")]
+    name: Option<String>,
+
+    /// only the nth request of the file, starting from 1
+    #[arg(long, group = "selector")]
+    index: Option<usize>,
+
+    /// only the request that contains the given line
+    #[arg(long, group = "selector")]
+    line: Option<u32>,
+}
+
+impl SelectArgs {
+    fn selector(&self) -> Option<Selector> {
+        if let Some(name) = &self.name {
+            Some(Selector::Name(name.clone()))
+        } else if let Some(index) = self.index {
+            Some(Selector::Index(index))
+        } else {
+            self.line.map(Selector::Line)
+        }
+    }
+}
 
 fn main() {
-    let filename = env::args().nth(1).unwrap();
-    let mut file = File::open(&filename)
+    let cli = Cli::parse();
+    match cli.command {
+        Command::Parse(args) => parse_file(&args),
+        Command::Run(args) => run_file(&args),
+        Command::Fmt(args) => process::exit(i32::from(!format_files(&args))),
+    }
+}
+
+fn read_source(filename: &str) -> String {
+    let mut file = File::open(filename)
         .map_err(|_e| format!("file not found: '{}'", filename))
         .unwrap();
 
@@ -15,17 +126,267 @@ fn main() {
         .map_err(|_e| "error reading file")
         .unwrap();
 
-    let requests = parser::parse(filename.as_str(), &source);
-    for i in requests.iter() {
-        println!("##############################");
-        println!("title: {:?}", i.title);
-        println!(
-            "method: {:?}, target: {:?}, version: {:?}",
-            i.method, i.target, i.version
-        );
-        println!("headers: {:?}", i.headers);
-        println!("body: {:?}", i.body);
-        println!("script: {:?}", i.script);
-        println!("##############################");
+    source
+}
+
+// parse the file and print its errors. returns true if there are errors, but
+// errors in other requests do not fail a selected request.
+fn parse_source<'a>(args: &'a SelectArgs, source: &'a str) -> (parser::HttpFile<'a>, bool) {
+    let file = parser::parse_with_recovery(args.file.as_str(), source);
+    for e in file.diagnostics.iter() {
+        eprint!("{}", diagnostic::render(e));
+    }
+    let failed = !file.diagnostics.is_empty() && args.selector().is_none();
+
+    (file, failed)
+}
+
+fn parse_file(args: &ParseArgs) {
+    let source = read_source(&args.select.file);
+    let (file, failed) = parse_source(&args.select, &source);
+
+    let mut file = file.into_owned();
+    if let Some(s) = &args.select.selector() {
+        file.requests = selected(&file.requests, s).into_iter().cloned().collect();
+    }
+    print_file(&file, args.format);
+    process::exit(i32::from(failed));
+}
+
+fn run_file(args: &FileArgs) {
+    let filename = &args.select.file;
+    let selector = args.select.selector();
+    let source = read_source(filename);
+    let (file, mut failed) = parse_source(&args.select, &source);
+
+    let mut env = match &args.env {
+        Some(name) => Environment::load(filename, name).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        }),
+        None => Environment::default(),
+    };
+    if let Some(seed) = args.seed {
+        env.dynamic = dynamic::Dynamic::with_seed(seed);
+    }
+
+    let requests: Vec<&Request> = match &selector {
+        Some(s) => selected(&file.requests, s),
+        None => file.requests.iter().collect(),
+    };
+    failed |= !run_items(&file, &requests, &mut env, |x, env| {
+        run_request(x, env, args.diff)
+    });
+
+    if failed {
+        process::exit(1);
+    }
+}
+
+// run the requests in file order, the variables declared before a request
+// are set when it runs. returns false if a request failed.
+fn run_items(
+    file: &parser::HttpFile,
+    requests: &[&Request],
+    env: &mut Environment,
+    mut run: impl FnMut(&Request, &mut Environment) -> bool,
+) -> bool {
+    let mut ok = true;
+    for item in file.items() {
+        match item {
+            Item::Variable(x) => env.declare(x),
+            Item::Request(x) if requests.iter().any(|r| std::ptr::eq(*r, x)) => {
+                ok &= run(x, env);
+            }
+            _ => {}
+        }
+    }
+    ok
+}
+
+// requests matching the selector, exits if there is none
+fn selected<'r, R: RequestInfo>(requests: &'r [R], selector: &Selector) -> Vec<&'r R> {
+    let requests = select::select(requests, selector);
+    if requests.is_empty() {
+        eprintln!("error: no request matches {}", selector);
+        process::exit(1);
+    }
+    requests
+}
+
+// returns false if request could not be sent or a test of the handler failed
+fn run_request(req: &Request, env: &mut Environment, diff: bool) -> bool {
+    env.request_variables.clear();
+    if !run_pre_request(req, env) {
+        return false;
+    }
+
+    // resolved once, dynamic variables get a new value on every resolve
+    let target = env.resolve(&req.target);
+    let result =
+        runner::build(req, &target, env).and_then(|x| client::execute(&x, &runner::options(req)));
+
+    match result {
+        Ok(res) => {
+            output(runner::print_response(&mut io::stdout().lock(), &res));
+            if diff {
+                print_diff(req, &res);
+            }
+            let saved = save_response(req, &res);
+            run_handler(req, &target, &res, env) && saved
+        }
+        Err(e) => {
+            eprintln!("error: {} {}: {}", req.method.as_str(), target, e);
+            false
+        }
+    }
+}
+
+// returns false if the response could not be saved
+fn save_response(req: &Request, res: &client::HttpResponse) -> bool {
+    match runner::save_response(req, res) {
+        Ok(Some(path)) => {
+            output(writeln!(
+                io::stdout(),
+                "response saved to {}",
+                path.display()
+            ));
+            true
+        }
+        Ok(None) => true,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            false
+        }
+    }
+}
+
+// differences do not fail the request, saved responses are only for reference
+fn print_diff(req: &Request, res: &client::HttpResponse) {
+    match runner::diff_response(req, res) {
+        Ok(Some(x)) if x.is_empty() => output(writeln!(
+            io::stdout(),
+            "response matches the saved response"
+        )),
+        Ok(Some(x)) => output(write!(io::stdout(), "{}", x)),
+        Ok(None) => {}
+        Err(e) => eprintln!("warning: {}", e),
+    }
+}
+
+// returns false if the handler could not be run or a test failed
+fn run_handler(
+    req: &Request,
+    target: &str,
+    res: &client::HttpResponse,
+    env: &mut Environment,
+) -> bool {
+    let result = runner::script_code(&req.script)
+        .map_err(|e| e.to_string())
+        .and_then(|code| match code {
+            Some(code) => script::run_handler(&code, res, &mut env.globals)
+                .map(Some)
+                .map_err(|e| e.to_string()),
+            None => Ok(None),
+        });
+
+    report_script(req, target, result)
+}
+
+// returns false if the pre-request script could not be run or a test failed
+fn run_pre_request(req: &Request, env: &mut Environment) -> bool {
+    let result = runner::script_code(&req.pre_script)
+        .map_err(|e| e.to_string())
+        .and_then(|code| match code {
+            Some(code) => script::run_pre_request(&code, &mut env.globals)
+                .map(|(report, variables)| {
+                    env.request_variables = variables;
+                    Some(report)
+                })
+                .map_err(|e| e.to_string()),
+            None => Ok(None),
+        });
+
+    // target is not resolved before the script sets the request variables
+    report_script(req, &req.target.to_string(), result)
+}
+
+fn report_script(
+    req: &Request,
+    target: &str,
+    result: Result<Option<script::Report>, String>,
+) -> bool {
+    match result {
+        Ok(Some(report)) => {
+            output(runner::print_report(&mut io::stdout().lock(), &report));
+            report.passed()
+        }
+        Ok(None) => true,
+        Err(e) => {
+            eprintln!("error: {} {}: {}", req.method.as_str(), target, e);
+            false
+        }
+    }
+}
+
+// returns false if a file could not be formatted or is not formatted in check mode
+fn format_files(args: &FmtArgs) -> bool {
+    let mut ok = true;
+    for path in args.files.iter() {
+        let source = match std::fs::read_to_string(path) {
+            Ok(x) => x,
+            Err(e) => {
+                eprintln!("error: {}: {}", path, e);
+                ok = false;
+                continue;
+            }
+        };
+
+        // files with syntax errors are left as they are
+        let file = parser::parse_with_recovery(path, &source);
+        if !file.diagnostics.is_empty() {
+            for e in file.diagnostics.iter() {
+                eprint!("{}", diagnostic::render(e));
+            }
+            ok = false;
+            continue;
+        }
+
+        let formatted = file.to_string();
+        if formatted == source {
+            continue;
+        }
+        if args.check {
+            output(writeln!(io::stdout(), "{} is not formatted", path));
+            ok = false;
+        } else if let Err(e) = std::fs::write(path, formatted) {
+            eprintln!("error: {}: {}", path, e);
+            ok = false;
+        }
+    }
+
+    ok
+}
+
+// print the file with only the requests to keep
+fn print_file(file: &parser::owned::HttpFile, format: dump::Format) {
+    match dump::dump(file, format) {
+        Ok(x) => output(writeln!(io::stdout(), "{}", x)),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+// exit quietly when stdout is closed, e.g. the output is piped to `head`
+fn output(result: io::Result<()>) {
+    match result {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => process::exit(0),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        }
     }
 }
@@ -0,0 +1,194 @@
+use crate::client::{Body, ClientError, HttpRequest, HttpResponse, Options};
+use crate::environment::Environment;
+use crate::script::Report;
+use parser::{BodyPart, DirectiveKind, MessageBody, Request, ScriptHandler, Span};
+use similar::TextDiff;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// paths in .http files are relative to the directory of the file.
+// `http_file` is the filename carried in the extra of the parsed spans.
+pub fn resolve_path(http_file: &str, path: &str) -> PathBuf {
+    let dir = Path::new(http_file)
+        .parent()
+        .unwrap_or_else(|| Path::new(""));
+    dir.join(path)
+}
+
+// build the request to send with the resolved target, resolving placeholders
+pub fn build(req: &Request, target: &str, env: &Environment) -> Result<HttpRequest, ClientError> {
+    let target = req
+        .target(target)
+        .map_err(|e| ClientError::InvalidUrl(e.to_string()))?;
+    let headers = req
+        .headers
+        .iter()
+        .map(|h| (h.name.fragment().to_string(), env.resolve(&h.value)))
+        .collect();
+
+    let mut body = Body::default();
+    match &req.body {
+        MessageBody::Multipart {
+            boundary, parts, ..
+        } => {
+            for part in parts.iter() {
+                body.push_bytes(format!("--{}\r\n", boundary));
+                for h in part.headers.iter() {
+                    body.push_bytes(format!("{}: {}\r\n", h.name, env.resolve(&h.value)));
+                }
+                body.push_bytes("\r\n");
+                build_body(&mut body, &part.body, env);
+                body.push_bytes("\r\n");
+            }
+            body.push_bytes(format!("--{}--\r\n", boundary));
+        }
+        x => build_body(&mut body, x, env),
+    }
+
+    Ok(HttpRequest {
+        method: req.method.as_str().to_string(),
+        target,
+        version: req.version.as_str().to_string(),
+        headers,
+        body,
+    })
+}
+
+// files of the body are only referenced, they are read when the request is sent
+fn build_body(body: &mut Body, message: &MessageBody, env: &Environment) {
+    match message {
+        MessageBody::Bytes(x) => body.push_bytes(env.resolve(x)),
+        MessageBody::File(path) => body.push_file(resolve_path(path.extra, path.fragment())),
+        MessageBody::Parts { parts, .. } => {
+            for part in parts.iter() {
+                match part {
+                    BodyPart::Inline(x) => body.push_bytes(env.resolve(x)),
+                    BodyPart::File(path) => {
+                        body.push_file(resolve_path(path.extra, path.fragment()))
+                    }
+                }
+            }
+        }
+        // parts cannot be nested
+        MessageBody::Empty | MessageBody::Multipart { .. } => {}
+    }
+}
+
+fn read_file(path: &Span) -> io::Result<Vec<u8>> {
+    let path = resolve_path(path.extra, path.fragment());
+    fs::read(&path).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+}
+
+// code of the script, scripts given with a path are read from the file
+pub fn script_code(script: &ScriptHandler) -> io::Result<Option<String>> {
+    match script {
+        ScriptHandler::Inline(x) => Ok(Some(x.fragment().to_string())),
+        ScriptHandler::File(path) => {
+            Ok(Some(String::from_utf8_lossy(&read_file(path)?).to_string()))
+        }
+        ScriptHandler::Empty => Ok(None),
+    }
+}
+
+// unified diff of the saved response linked with the first `<> path` line and
+// the body of the new response. returns None if the request has no reference.
+pub fn diff_response(req: &Request, res: &HttpResponse) -> io::Result<Option<String>> {
+    let path = match req.response_refs.first() {
+        Some(x) => &x.path,
+        None => return Ok(None),
+    };
+
+    let saved = read_file(path)?;
+    let saved = String::from_utf8_lossy(&saved);
+    let body = String::from_utf8_lossy(&res.body);
+
+    Ok(Some(
+        TextDiff::from_lines(saved.as_ref(), body.as_ref())
+            .unified_diff()
+            .header(path.fragment(), "response")
+            .to_string(),
+    ))
+}
+
+// write the response body to the file of the `>> path` line, returns the path
+// of the written file. existing files get a `-1`, `-2`... suffix unless the
+// redirect is given as `>>! path`.
+pub fn save_response(req: &Request, res: &HttpResponse) -> io::Result<Option<PathBuf>> {
+    let redirect = match &req.redirect {
+        Some(x) => x,
+        None => return Ok(None),
+    };
+
+    let mut path = resolve_path(redirect.path.extra, redirect.path.fragment().trim());
+    if !redirect.overwrite {
+        path = unused_path(&path);
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, &res.body)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+
+    Ok(Some(path))
+}
+
+fn unused_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|x| format!(".{}", x.to_string_lossy()));
+
+    let mut candidate = path.to_path_buf();
+    let mut n = 1;
+    while candidate.exists() {
+        let name = format!("{}-{}{}", stem, n, extension.as_deref().unwrap_or_default());
+        candidate = path.with_file_name(name);
+        n += 1;
+    }
+
+    candidate
+}
+
+// client options given with directives
+pub fn options(req: &Request) -> Options {
+    let mut opts = Options::default();
+    for d in req.directives.iter() {
+        match d.kind {
+            DirectiveKind::NoRedirect => opts.max_redirects = 0,
+            DirectiveKind::Timeout(x) => opts.timeout = Some(x),
+            DirectiveKind::ConnectionTimeout(x) => opts.connection_timeout = Some(x),
+            _ => {}
+        }
+    }
+
+    opts
+}
+
+pub fn print_response<W: Write>(w: &mut W, res: &HttpResponse) -> io::Result<()> {
+    writeln!(w, "{} {} {}", res.version, res.status, res.reason)?;
+    for (name, value) in res.headers.iter() {
+        writeln!(w, "{}: {}", name, value)?;
+    }
+    writeln!(w)?;
+    w.write_all(&res.body)?;
+    if !res.body.is_empty() && !res.body.ends_with(b"\n") {
+        writeln!(w)?;
+    }
+
+    Ok(())
+}
+
+pub fn print_report<W: Write>(w: &mut W, report: &Report) -> io::Result<()> {
+    for line in report.logs.iter() {
+        writeln!(w, "{}", line)?;
+    }
+    for t in report.tests.iter() {
+        match &t.error {
+            None => writeln!(w, "test {} ... ok", t.name)?,
+            Some(e) => writeln!(w, "test {} ... FAILED: {}", t.name, e)?,
+        }
+    }
+
+    Ok(())
+}
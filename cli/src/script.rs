@@ -0,0 +1,212 @@
+use crate::client::HttpResponse;
+use boa_engine::{Context, Source};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fmt;
+
+// `client` object of the JetBrains HTTP client. state is kept in plain JS
+// objects and read back as JSON after the handler runs.
+const PRELUDE: &str = r#"
+const client = {
+    global: {
+        _data: __globals,
+        set(name, value) { this._data[name] = String(value); },
+        get(name) { return name in this._data ? this._data[name] : null; },
+        isEmpty() { return Object.keys(this._data).length === 0; },
+        clear(name) { delete this._data[name]; },
+        clearAll() { this._data = {}; },
+    },
+    _tests: [],
+    _logs: [],
+    test(name, fn) {
+        try {
+            fn();
+            this._tests.push({ name: String(name), error: null });
+        } catch (e) {
+            this._tests.push({ name: String(name), error: String(e && e.message || e) });
+        }
+    },
+    assert(condition, message) {
+        if (!condition) {
+            throw new Error(message === undefined ? "assertion failed" : String(message));
+        }
+    },
+    log(...args) { this._logs.push(args.map(String).join(" ")); },
+};
+"#;
+
+// `response` object given to the response handlers
+const RESPONSE: &str = r#"
+const response = {
+    status: __response.status,
+    body: __response.body,
+    contentType: __response.contentType,
+    headers: {
+        _list: __response.headers,
+        valueOf(name) {
+            const h = this._list.find(x => x[0].toLowerCase() === name.toLowerCase());
+            return h ? h[1] : null;
+        },
+        valuesOf(name) {
+            return this._list.filter(x => x[0].toLowerCase() === name.toLowerCase()).map(x => x[1]);
+        },
+    },
+};
+"#;
+
+// `request` object given to the pre-request scripts
+const REQUEST: &str = r#"
+const request = {
+    variables: {
+        _data: {},
+        set(name, value) { this._data[name] = String(value); },
+        get(name) { return name in this._data ? this._data[name] : null; },
+    },
+};
+"#;
+
+const RESULT: &str = r#"
+JSON.stringify({
+    globals: client.global._data,
+    tests: client._tests,
+    logs: client._logs,
+    variables: typeof request === "undefined" ? {} : request.variables._data,
+})
+"#;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptError(pub String);
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "script error: {}", self.0)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestResult {
+    pub name: String,
+    /// None if the test passed
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Report {
+    pub tests: Vec<TestResult>,
+    /// lines written with `client.log`
+    pub logs: Vec<String>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.tests.iter().all(|t| t.error.is_none())
+    }
+}
+
+// run the response handler. `globals` holds the variables set with
+// `client.global.set` and is updated with the changes of the handler.
+pub fn run_handler(
+    code: &str,
+    res: &HttpResponse,
+    globals: &mut HashMap<String, String>,
+) -> Result<Report, ScriptError> {
+    let setup = format!("const __response = {};\n{}", response_json(res), RESPONSE);
+    let (report, _) = run(&setup, code, globals)?;
+    Ok(report)
+}
+
+// run the pre-request script, returns the report and the variables set with
+// `request.variables.set`
+pub fn run_pre_request(
+    code: &str,
+    globals: &mut HashMap<String, String>,
+) -> Result<(Report, HashMap<String, String>), ScriptError> {
+    run(REQUEST, code, globals)
+}
+
+fn run(
+    setup: &str,
+    code: &str,
+    globals: &mut HashMap<String, String>,
+) -> Result<(Report, HashMap<String, String>), ScriptError> {
+    let setup = format!(
+        "const __globals = {};\n{}\n{}",
+        json!(globals),
+        PRELUDE,
+        setup
+    );
+
+    let mut ctx = Context::default();
+    let eval = |ctx: &mut Context, code: &str| {
+        ctx.eval(Source::from_bytes(code))
+            .map_err(|e| ScriptError(e.to_string()))
+    };
+    eval(&mut ctx, &setup)?;
+    eval(&mut ctx, code)?;
+
+    let result = eval(&mut ctx, RESULT)?
+        .to_string(&mut ctx)
+        .map_err(|e| ScriptError(e.to_string()))?
+        .to_std_string_escaped();
+    let result: Value = serde_json::from_str(&result).map_err(|e| ScriptError(e.to_string()))?;
+
+    *globals = string_map(&result["globals"]);
+
+    let tests = result["tests"]
+        .as_array()
+        .map(|x| {
+            x.iter()
+                .map(|t| TestResult {
+                    name: t["name"].as_str().unwrap_or_default().to_string(),
+                    error: t["error"].as_str().map(String::from),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let logs = result["logs"]
+        .as_array()
+        .map(|x| {
+            x.iter()
+                .map(|l| l.as_str().unwrap_or_default().to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok((Report { tests, logs }, string_map(&result["variables"])))
+}
+
+fn string_map(value: &Value) -> HashMap<String, String> {
+    match value {
+        Value::Object(x) => x
+            .iter()
+            .map(|(k, v)| (k.clone(), v.as_str().unwrap_or_default().to_string()))
+            .collect(),
+        _ => HashMap::new(),
+    }
+}
+
+// body is parsed if the response is json, otherwise it is given as text
+fn response_json(res: &HttpResponse) -> Value {
+    let content_type = res.header("Content-Type").unwrap_or_default();
+    let mut params = content_type.split(';').map(str::trim);
+    let mime_type = params.next().unwrap_or_default().to_ascii_lowercase();
+    let charset = params
+        .find_map(|x| x.strip_prefix("charset="))
+        .unwrap_or("utf-8");
+
+    let text = String::from_utf8_lossy(&res.body);
+    let body = if mime_type == "application/json" || mime_type.ends_with("+json") {
+        serde_json::from_str(&text).unwrap_or_else(|_| Value::String(text.to_string()))
+    } else {
+        Value::String(text.to_string())
+    };
+
+    json!({
+        "status": res.status,
+        "body": body,
+        "contentType": { "mimeType": mime_type, "charset": charset },
+        "headers": res.headers,
+    })
+}
@@ -0,0 +1,44 @@
+use parser::RequestInfo;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Selector {
+    /// `# @name` or title of the request
+    Name(String),
+    /// 1-based position of the request in the file
+    Index(usize),
+    /// 1-based line number within the request
+    Line(u32),
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Selector::Name(x) => write!(f, "name {:?}", x),
+            Selector::Index(x) => write!(f, "index {}", x),
+            Selector::Line(x) => write!(f, "line {}", x),
+        }
+    }
+}
+
+pub fn select<'r, R: RequestInfo>(requests: &'r [R], selector: &Selector) -> Vec<&'r R> {
+    match selector {
+        Selector::Name(name) => requests
+            .iter()
+            .filter(|r| r.name() == Some(name.as_str()))
+            .collect(),
+        Selector::Index(index) => index
+            .checked_sub(1)
+            .and_then(|i| requests.get(i))
+            .into_iter()
+            .collect(),
+        Selector::Line(line) => {
+            // a request covers the lines from its start to the start of the next one
+            let pos = requests.partition_point(|r| r.start_line() <= *line);
+            pos.checked_sub(1)
+                .and_then(|i| requests.get(i))
+                .into_iter()
+                .collect()
+        }
+    }
+}
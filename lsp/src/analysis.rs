@@ -0,0 +1,272 @@
+use lsp_types::{
+    CodeLens, Command, Diagnostic, DiagnosticSeverity, DocumentSymbol, Hover, HoverContents,
+    Location, MarkupContent, MarkupKind, Position, Range, SymbolKind, Url,
+};
+use parser::cst::SyntaxKind;
+use parser::{
+    BodyPart, HttpFile, MessageBody, ParseError, Request, RequestInfo, ScriptHandler, Span,
+    PRIVATE_ENV_FILE, PUBLIC_ENV_FILE,
+};
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+
+/// Command of the "Run request" code lens, arguments are the document uri
+/// and the 1-based index of the request like `restman run --index`.
+pub const RUN_COMMAND: &str = "restman.run";
+
+// descriptions of the common headers shown on hover
+const HEADERS: &[(&str, &str)] = &[
+    ("accept", "Media types the client can understand."),
+    (
+        "accept-encoding",
+        "Content encodings the client can understand.",
+    ),
+    ("accept-language", "Natural languages the client prefers."),
+    (
+        "authorization",
+        "Credentials to authenticate the client with the server.",
+    ),
+    (
+        "cache-control",
+        "Caching directives for requests and responses.",
+    ),
+    (
+        "connection",
+        "Whether the connection stays open after the request.",
+    ),
+    ("content-length", "Size of the body in bytes."),
+    ("content-type", "Media type of the body."),
+    ("cookie", "Cookies previously sent by the server."),
+    ("host", "Host and port of the server."),
+    (
+        "if-none-match",
+        "Makes the request conditional on the ETag of the resource.",
+    ),
+    ("origin", "Origin that caused the request."),
+    ("user-agent", "Identifies the client software."),
+];
+
+// LSP position of the byte offset, characters are counted in UTF-16 code units
+pub fn position(text: &str, offset: usize) -> Position {
+    let before = &text[..offset];
+    let line = before.matches('\n').count();
+    let line_start = before.rfind('\n').map_or(0, |x| x + 1);
+    let character = text[line_start..offset].encode_utf16().count();
+
+    Position::new(line as u32, character as u32)
+}
+
+// byte offset of the LSP position, None if the line does not exist
+pub fn offset(text: &str, pos: Position) -> Option<usize> {
+    let line_start = match pos.line {
+        0 => 0,
+        n => text.match_indices('\n').nth(n as usize - 1)?.0 + 1,
+    };
+
+    let mut units = 0;
+    for (i, c) in text[line_start..].char_indices() {
+        if units >= pos.character || c == '\n' {
+            return Some(line_start + i);
+        }
+        units += c.len_utf16() as u32;
+    }
+
+    Some(text.len())
+}
+
+fn span_range(text: &str, span: &Span) -> Range {
+    let start = span.location_offset();
+    Range::new(position(text, start), position(text, start + span.len()))
+}
+
+fn contains(span: &Span, offset: usize) -> bool {
+    let start = span.location_offset();
+    (start..=start + span.len()).contains(&offset)
+}
+
+pub fn diagnostics(file: &HttpFile) -> Vec<Diagnostic> {
+    file.diagnostics.iter().map(diagnostic).collect()
+}
+
+fn diagnostic(e: &ParseError) -> Diagnostic {
+    let utf16 = |chars: usize| -> u32 {
+        e.snippet
+            .chars()
+            .take(chars)
+            .map(|c| c.len_utf16() as u32)
+            .sum()
+    };
+    let line = e.line.saturating_sub(1);
+
+    Diagnostic {
+        range: Range::new(
+            Position::new(line, utf16(e.column.saturating_sub(1))),
+            Position::new(line, utf16(usize::MAX)),
+        ),
+        severity: Some(DiagnosticSeverity::ERROR),
+        source: Some("restman".to_string()),
+        message: e.message.clone(),
+        ..Default::default()
+    }
+}
+
+// outline of the file, one symbol for each request
+pub fn symbols(file: &HttpFile, text: &str) -> Vec<DocumentSymbol> {
+    let tree = file.syntax_tree();
+    let nodes = tree
+        .children
+        .iter()
+        .filter(|x| x.kind == SyntaxKind::Request);
+
+    file.requests
+        .iter()
+        .zip(nodes)
+        .map(|(r, node)| {
+            let line = format!("{} {}", r.method.as_str(), r.target.span.fragment());
+            let selection = r.title.unwrap_or(r.line);
+
+            #[allow(deprecated)]
+            DocumentSymbol {
+                name: r
+                    .name()
+                    .filter(|x| !x.is_empty())
+                    .unwrap_or(&line)
+                    .to_string(),
+                detail: Some(line.clone()),
+                kind: SymbolKind::METHOD,
+                tags: None,
+                deprecated: None,
+                range: span_range(text, &node.span),
+                selection_range: span_range(text, &selection),
+                children: None,
+            }
+        })
+        .collect()
+}
+
+// declaration of the `{{variable}}` or the file of the `< path` under the cursor.
+// a variable is declared by the nearest declaration before it, like the cli
+// sets them when it runs the requests in order.
+pub fn definition(file: &HttpFile, text: &str, uri: &Url, pos: Position) -> Option<Location> {
+    let offset = offset(text, pos)?;
+    // env files and referenced files are next to the document
+    let dir = || Some(uri.to_file_path().ok()?.parent()?.to_path_buf());
+
+    let mut variables: Vec<&Span> = file.requests.iter().flat_map(|r| r.variables()).collect();
+    variables.extend(file.variables.iter().flat_map(|x| x.value.variables()));
+    if let Some(name) = variables.into_iter().find(|x| contains(x, offset)) {
+        if let Some(decl) = file
+            .variables
+            .iter()
+            .rev()
+            .filter(|x| x.span.location_offset() < name.location_offset())
+            .find(|x| x.name.fragment() == name.fragment())
+        {
+            return Some(Location::new(uri.clone(), span_range(text, &decl.name)));
+        }
+
+        let dir = dir()?;
+        return [PUBLIC_ENV_FILE, PRIVATE_ENV_FILE]
+            .iter()
+            .find_map(|x| env_variable(&dir.join(x), name.fragment()));
+    }
+
+    let path = file
+        .requests
+        .iter()
+        .flat_map(file_refs)
+        .find(|x| contains(x, offset))?;
+    let uri = Url::from_file_path(dir()?.join(path.fragment().trim())).ok()?;
+
+    Some(Location::new(uri, Range::default()))
+}
+
+// `"name":` key in the env file
+fn env_variable(path: &Path, name: &str) -> Option<Location> {
+    let text = fs::read_to_string(path).ok()?;
+    let key = format!("\"{}\"", name);
+    let (start, _) = text
+        .match_indices(&key)
+        .find(|(x, _)| text[x + key.len()..].trim_start().starts_with(':'))?;
+    let range = Range::new(position(&text, start), position(&text, start + key.len()));
+
+    Some(Location::new(Url::from_file_path(path).ok()?, range))
+}
+
+// paths of the files used by the request
+fn file_refs<'f, 'a>(r: &'f Request<'a>) -> Vec<&'f Span<'a>> {
+    let mut refs = vec![];
+    for script in [&r.pre_script, &r.script] {
+        if let ScriptHandler::File(x) = script {
+            refs.push(x);
+        }
+    }
+    body_refs(&r.body, &mut refs);
+    refs.extend(r.response_refs.iter().map(|x| &x.path));
+    refs.extend(r.redirect.iter().map(|x| &x.path));
+
+    refs
+}
+
+fn body_refs<'f, 'a>(body: &'f MessageBody<'a>, refs: &mut Vec<&'f Span<'a>>) {
+    match body {
+        MessageBody::File(x) => refs.push(x),
+        MessageBody::Parts { parts, .. } => refs.extend(parts.iter().filter_map(|x| match x {
+            BodyPart::File(x) => Some(x),
+            BodyPart::Inline(_) => None,
+        })),
+        MessageBody::Multipart { parts, .. } => {
+            parts.iter().for_each(|x| body_refs(&x.body, refs));
+        }
+        MessageBody::Bytes(_) | MessageBody::Empty => {}
+    }
+}
+
+// description of the header under the cursor
+pub fn hover(file: &HttpFile, text: &str, pos: Position) -> Option<Hover> {
+    let offset = offset(text, pos)?;
+    let header = file
+        .requests
+        .iter()
+        .flat_map(|r| r.headers.iter())
+        .find(|h| {
+            let start = h.name.location_offset();
+            let end = h.value.span.location_offset() + h.value.span.len();
+            (start..=end).contains(&offset)
+        })?;
+
+    let name = header.name.fragment();
+    let mut value = format!("**{}**: `{}`", name, header.value.span.fragment().trim());
+    if let Some((_, description)) = HEADERS.iter().find(|x| x.0.eq_ignore_ascii_case(name)) {
+        value.push_str("\n\n");
+        value.push_str(description);
+    }
+
+    let start = header.name.location_offset();
+    let end = header.value.span.location_offset() + header.value.span.len();
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: Some(Range::new(position(text, start), position(text, end))),
+    })
+}
+
+// "Run request" above each request line
+pub fn code_lenses(file: &HttpFile, text: &str, uri: &Url) -> Vec<CodeLens> {
+    file.requests
+        .iter()
+        .enumerate()
+        .map(|(i, r)| CodeLens {
+            range: span_range(text, &r.line),
+            command: Some(Command {
+                title: "Run request".to_string(),
+                command: RUN_COMMAND.to_string(),
+                arguments: Some(vec![json!(uri), json!(i + 1)]),
+            }),
+            data: None,
+        })
+        .collect()
+}
@@ -0,0 +1,21 @@
+mod analysis;
+mod server;
+#[cfg(test)]
+mod tests;
+
+use lsp_server::Connection;
+use std::process;
+
+fn main() {
+    let (conn, io_threads) = Connection::stdio();
+    if let Err(e) = server::run(&conn) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+
+    drop(conn);
+    io_threads.join().unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    });
+}
@@ -0,0 +1,286 @@
+use crate::analysis;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+    Notification as NotificationType, PublishDiagnostics,
+};
+use lsp_types::request::{
+    CodeLensRequest, DocumentSymbolRequest, ExecuteCommand, GotoDefinition, HoverRequest,
+    Request as RequestType,
+};
+use lsp_types::{
+    CodeLensOptions, CodeLensParams, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, DocumentSymbolParams, DocumentSymbolResponse, ExecuteCommandOptions,
+    ExecuteCommandParams, GotoDefinitionParams, GotoDefinitionResponse, HoverParams,
+    HoverProviderCapability, OneOf, PublishDiagnosticsParams, ServerCapabilities,
+    TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::process;
+use std::thread;
+
+type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+// cli that runs the requests of the code lenses
+const RESTMAN: &str = "restman";
+
+// settings the client gives in the `initializationOptions` of initialize
+struct Options {
+    // path of the cli, `restmanPath`
+    restman: String,
+}
+
+impl Options {
+    fn from_params(params: &Value) -> Self {
+        let options = &params["initializationOptions"];
+        Options {
+            restman: options["restmanPath"]
+                .as_str()
+                .unwrap_or(RESTMAN)
+                .to_string(),
+        }
+    }
+}
+
+pub fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        code_lens_provider: Some(CodeLensOptions {
+            resolve_provider: Some(false),
+        }),
+        execute_command_provider: Some(ExecuteCommandOptions {
+            commands: vec![analysis::RUN_COMMAND.to_string()],
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+// serve the client until it sends shutdown
+pub fn run(conn: &Connection) -> Result<()> {
+    let params = conn.initialize(serde_json::to_value(capabilities())?)?;
+    let options = Options::from_params(&params);
+
+    // text of the open documents
+    let mut docs: HashMap<Url, String> = HashMap::new();
+    for msg in &conn.receiver {
+        match msg {
+            Message::Request(req) => {
+                if conn.handle_shutdown(&req)? {
+                    return Ok(());
+                }
+                // a run waits for the response of the server, the other
+                // requests are answered meanwhile
+                if req.method == ExecuteCommand::METHOD {
+                    let id = req.id.clone();
+                    let command = run_command(&options, &docs, req.params);
+                    let sender = conn.sender.clone();
+                    thread::spawn(move || {
+                        sender.send(Message::Response(execute_command(id, command)))
+                    });
+                    continue;
+                }
+                conn.sender
+                    .send(Message::Response(handle_request(&docs, req)))?;
+            }
+            Message::Notification(n) => {
+                // notifications have no response, a broken one is only logged
+                let method = n.method.clone();
+                match handle_notification(&mut docs, n) {
+                    Ok(Some(uri)) => publish_diagnostics(conn, &docs, uri)?,
+                    Ok(None) => {}
+                    Err(e) => eprintln!("error: invalid {} notification: {}", method, e),
+                }
+            }
+            Message::Response(_) => {}
+        }
+    }
+
+    Ok(())
+}
+
+// returns the document to publish diagnostics for
+fn handle_notification(docs: &mut HashMap<Url, String>, n: Notification) -> Result<Option<Url>> {
+    match n.method.as_str() {
+        DidOpenTextDocument::METHOD => {
+            let p: DidOpenTextDocumentParams = serde_json::from_value(n.params)?;
+            docs.insert(p.text_document.uri.clone(), p.text_document.text);
+            Ok(Some(p.text_document.uri))
+        }
+        DidChangeTextDocument::METHOD => {
+            // documents are synced in full, the last change is the whole text
+            let p: DidChangeTextDocumentParams = serde_json::from_value(n.params)?;
+            match p.content_changes.into_iter().last() {
+                Some(change) => {
+                    docs.insert(p.text_document.uri.clone(), change.text);
+                    Ok(Some(p.text_document.uri))
+                }
+                None => Ok(None),
+            }
+        }
+        DidCloseTextDocument::METHOD => {
+            let p: DidCloseTextDocumentParams = serde_json::from_value(n.params)?;
+            docs.remove(&p.text_document.uri);
+            Ok(None)
+        }
+        _ => Ok(None),
+    }
+}
+
+fn publish_diagnostics(conn: &Connection, docs: &HashMap<Url, String>, uri: Url) -> Result<()> {
+    let text = docs.get(&uri).map_or("", String::as_str);
+    let path = uri.path().to_string();
+    let file = parser::parse_with_recovery(&path, text);
+
+    let params = PublishDiagnosticsParams {
+        uri,
+        diagnostics: analysis::diagnostics(&file),
+        version: None,
+    };
+    let n = Notification::new(PublishDiagnostics::METHOD.to_string(), params);
+    conn.sender.send(Message::Notification(n))?;
+
+    Ok(())
+}
+
+fn handle_request(docs: &HashMap<Url, String>, req: Request) -> Response {
+    let id = req.id.clone();
+    let result = match req.method.as_str() {
+        DocumentSymbolRequest::METHOD => {
+            with_document(docs, req, |_: DocumentSymbolParams, file, text| {
+                let symbols = analysis::symbols(file, text);
+                serde_json::to_value(DocumentSymbolResponse::Nested(symbols))
+            })
+        }
+        GotoDefinition::METHOD => {
+            with_document(docs, req, |p: GotoDefinitionParams, file, text| {
+                let doc = p.text_document_position_params;
+                let location =
+                    analysis::definition(file, text, &doc.text_document.uri, doc.position);
+                serde_json::to_value(location.map(GotoDefinitionResponse::Scalar))
+            })
+        }
+        HoverRequest::METHOD => with_document(docs, req, |p: HoverParams, file, text| {
+            let doc = p.text_document_position_params;
+            serde_json::to_value(analysis::hover(file, text, doc.position))
+        }),
+        CodeLensRequest::METHOD => with_document(docs, req, |p: CodeLensParams, file, text| {
+            serde_json::to_value(analysis::code_lenses(file, text, &p.text_document.uri))
+        }),
+        method => {
+            return Response::new_err(
+                id,
+                ErrorCode::MethodNotFound as i32,
+                format!("unknown method {}", method),
+            )
+        }
+    };
+
+    match result {
+        Ok(x) => Response::new_ok(id, x),
+        Err(e) => Response::new_err(id, ErrorCode::InvalidParams as i32, e.to_string()),
+    }
+}
+
+// the output of the cli is the result
+fn execute_command(id: RequestId, command: Result<process::Command>) -> Response {
+    let result = command.and_then(|mut command| {
+        let program = command.get_program().to_string_lossy().into_owned();
+        let output = command
+            .output()
+            .map_err(|e| format!("error running {}: {}", program, e))?;
+        match output.status.success() {
+            true => Ok(Value::from(String::from_utf8_lossy(&output.stdout))),
+            false => Err(String::from_utf8_lossy(&output.stderr).trim().into()),
+        }
+    });
+
+    match result {
+        Ok(x) => Response::new_ok(id, x),
+        Err(e) => Response::new_err(id, ErrorCode::RequestFailed as i32, e.to_string()),
+    }
+}
+
+// cli command that runs the request of the "Run request" code lens. the cli
+// runs the saved file, so the index of the code lens is only valid if the
+// open document is saved.
+fn run_command(
+    options: &Options,
+    docs: &HashMap<Url, String>,
+    params: Value,
+) -> Result<process::Command> {
+    let p: ExecuteCommandParams = serde_json::from_value(params)?;
+    if p.command != analysis::RUN_COMMAND {
+        return Err(format!("unknown command {}", p.command).into());
+    }
+    let (uri, index): (Url, usize) = serde_json::from_value(Value::Array(p.arguments))?;
+    let path = uri
+        .to_file_path()
+        .map_err(|_| format!("{} is not a file", uri))?;
+    if let Some(text) = docs.get(&uri) {
+        let saved = fs::read_to_string(&path)
+            .map_err(|e| format!("error reading {}: {}", path.display(), e))?;
+        if saved != *text {
+            return Err(format!("{} has unsaved changes, save it to run", path.display()).into());
+        }
+    }
+
+    let mut command = process::Command::new(&options.restman);
+    command
+        .arg("run")
+        .arg("--index")
+        .arg(index.to_string())
+        .arg(&path);
+    Ok(command)
+}
+
+// parse the params and the document the request is about
+fn with_document<P, F>(docs: &HashMap<Url, String>, req: Request, f: F) -> Result<Value>
+where
+    P: DeserializeOwned + HasDocument,
+    F: FnOnce(P, &parser::HttpFile, &str) -> serde_json::Result<Value>,
+{
+    let params: P = serde_json::from_value(req.params)?;
+    let uri = params.uri().clone();
+    let text = docs.get(&uri).map_or("", String::as_str);
+    let path = uri.path().to_string();
+    let file = parser::parse_with_recovery(&path, text);
+
+    Ok(f(params, &file, text)?)
+}
+
+// uri of the document of the request params
+trait HasDocument {
+    fn uri(&self) -> &Url;
+}
+
+impl HasDocument for DocumentSymbolParams {
+    fn uri(&self) -> &Url {
+        &self.text_document.uri
+    }
+}
+
+impl HasDocument for GotoDefinitionParams {
+    fn uri(&self) -> &Url {
+        &self.text_document_position_params.text_document.uri
+    }
+}
+
+impl HasDocument for HoverParams {
+    fn uri(&self) -> &Url {
+        &self.text_document_position_params.text_document.uri
+    }
+}
+
+impl HasDocument for CodeLensParams {
+    fn uri(&self) -> &Url {
+        &self.text_document.uri
+    }
+}
@@ -0,0 +1,352 @@
+#[cfg(test)]
+mod test {
+    use crate::analysis::*;
+    use crate::server;
+    use lsp_server::{
+        Connection, Message, Notification, Request, RequestId, Response, ResponseError,
+    };
+    use lsp_types::{Position, Url};
+    use parser::PUBLIC_ENV_FILE;
+    use serde_json::{json, Value};
+    use std::fs;
+    use std::path::PathBuf;
+    use std::thread;
+
+    const SOURCE: &str = "@host = localhost
+
+### List users
+# @name list
+GET http://{{host}}/users
+Accept: application/json
+
+### Create user
+POST http://{{host}}/users
+Content-Type: application/json
+
+< ./user.json
+
+> ./check.js
+";
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("restman-lsp-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // client side of a scripted session with a server running in a thread
+    struct Session {
+        client: Connection,
+        server: Option<thread::JoinHandle<()>>,
+        next_id: i32,
+    }
+
+    impl Session {
+        fn start() -> Self {
+            Self::start_with(Value::Null)
+        }
+
+        fn start_with(options: Value) -> Self {
+            let (server, client) = Connection::memory();
+            let handle = thread::spawn(move || server::run(&server).unwrap());
+            let mut session = Session {
+                client,
+                server: Some(handle),
+                next_id: 0,
+            };
+
+            let result = session.request(
+                "initialize",
+                json!({ "capabilities": {}, "initializationOptions": options }),
+            );
+            assert_eq!(result["capabilities"]["hoverProvider"], true);
+            assert_eq!(
+                result["capabilities"]["executeCommandProvider"]["commands"],
+                json!([RUN_COMMAND])
+            );
+            session.notify("initialized", json!({}));
+
+            session
+        }
+
+        fn send(&mut self, method: &str, params: Value) -> Response {
+            self.next_id += 1;
+            let id = RequestId::from(self.next_id);
+            let req = Request::new(id.clone(), method.to_string(), params);
+            self.client.sender.send(Message::Request(req)).unwrap();
+
+            match self.client.receiver.recv().unwrap() {
+                Message::Response(res) if res.id == id => res,
+                x => panic!("unexpected message {:?}", x),
+            }
+        }
+
+        fn request(&mut self, method: &str, params: Value) -> Value {
+            match self.send(method, params) {
+                Response {
+                    result: Some(result),
+                    error: None,
+                    ..
+                } => result,
+                x => panic!("unexpected response {:?}", x),
+            }
+        }
+
+        fn request_error(&mut self, method: &str, params: Value) -> ResponseError {
+            match self.send(method, params) {
+                Response {
+                    error: Some(error), ..
+                } => error,
+                x => panic!("unexpected response {:?}", x),
+            }
+        }
+
+        fn notify(&self, method: &str, params: Value) {
+            let n = Notification::new(method.to_string(), params);
+            self.client.sender.send(Message::Notification(n)).unwrap();
+        }
+
+        fn receive_notification(&self) -> Notification {
+            match self.client.receiver.recv().unwrap() {
+                Message::Notification(n) => n,
+                x => panic!("unexpected message {:?}", x),
+            }
+        }
+
+        fn open(&self, uri: &Url, text: &str) -> Value {
+            self.notify(
+                "textDocument/didOpen",
+                json!({
+                    "textDocument": { "uri": uri, "languageId": "http", "version": 1, "text": text }
+                }),
+            );
+            let n = self.receive_notification();
+            assert_eq!(n.method, "textDocument/publishDiagnostics");
+            n.params
+        }
+
+        fn shutdown(mut self) {
+            self.request("shutdown", Value::Null);
+            self.notify("exit", Value::Null);
+            self.server.take().unwrap().join().unwrap();
+        }
+    }
+
+    fn at(uri: &Url, line: u32, character: u32) -> Value {
+        json!({
+            "textDocument": { "uri": uri },
+            "position": { "line": line, "character": character }
+        })
+    }
+
+    #[test]
+    fn it_should_convert_offsets_to_utf16_positions() {
+        let text = "ab\n€𝄞x\n";
+
+        assert_eq!(position(text, 0), Position::new(0, 0));
+        assert_eq!(position(text, 3), Position::new(1, 0));
+        assert_eq!(position(text, 10), Position::new(1, 3));
+        assert_eq!(offset(text, Position::new(1, 3)), Some(10));
+        assert_eq!(offset(text, Position::new(0, 10)), Some(2));
+        assert_eq!(offset(text, Position::new(5, 0)), None);
+    }
+
+    #[test]
+    fn it_should_publish_diagnostics_and_list_requests() {
+        let dir = test_dir("symbols");
+        let uri = Url::from_file_path(dir.join("api.http")).unwrap();
+        let mut session = Session::start();
+
+        let params = session.open(&uri, "### Broken\n{foo}\n\n### Ok\nGET /a\n");
+        assert_eq!(params["diagnostics"][0]["message"], "expected method");
+        assert_eq!(
+            params["diagnostics"][0]["range"]["start"],
+            json!({"line": 1, "character": 0})
+        );
+
+        session.notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": uri, "version": 2 },
+                "contentChanges": [{ "text": SOURCE }]
+            }),
+        );
+        let n = session.receive_notification();
+        assert_eq!(n.params["diagnostics"], json!([]));
+
+        let symbols = session.request(
+            "textDocument/documentSymbol",
+            json!({ "textDocument": { "uri": uri } }),
+        );
+        assert_eq!(symbols[0]["name"], "list");
+        assert_eq!(symbols[0]["detail"], "GET http://{{host}}/users");
+        assert_eq!(
+            symbols[0]["range"]["start"],
+            json!({"line": 2, "character": 0})
+        );
+        assert_eq!(symbols[1]["name"], "Create user");
+        assert_eq!(
+            symbols[1]["selectionRange"]["start"],
+            json!({"line": 7, "character": 4})
+        );
+
+        let lenses = session.request(
+            "textDocument/codeLens",
+            json!({ "textDocument": { "uri": uri } }),
+        );
+        assert_eq!(lenses.as_array().unwrap().len(), 2);
+        assert_eq!(
+            lenses[1]["range"]["start"],
+            json!({"line": 8, "character": 0})
+        );
+        assert_eq!(lenses[1]["command"]["command"], RUN_COMMAND);
+        assert_eq!(lenses[1]["command"]["arguments"], json!([uri, 2]));
+
+        session.shutdown();
+    }
+
+    #[test]
+    fn it_should_go_to_definitions() {
+        let dir = test_dir("definition");
+        fs::write(
+            dir.join(PUBLIC_ENV_FILE),
+            "{\n  \"dev\": {\n    \"token\": \"abc\"\n  }\n}\n",
+        )
+        .unwrap();
+        let uri = Url::from_file_path(dir.join("api.http")).unwrap();
+        let mut session = Session::start();
+        session.open(
+            &uri,
+            &format!(
+                "{}\n### Auth\nGET /me\nAuthorization: {{{{token}}}}\n\n### Other\n@host = example.com\nGET http://{{{{host}}}}/\n",
+                SOURCE
+            ),
+        );
+
+        // `{{host}}` of the first request
+        let location = session.request("textDocument/definition", at(&uri, 4, 14));
+        assert_eq!(location["uri"], json!(uri));
+        assert_eq!(
+            location["range"],
+            json!({"start": {"line": 0, "character": 1}, "end": {"line": 0, "character": 5}})
+        );
+        // `{{host}}` after it is declared again
+        let location = session.request("textDocument/definition", at(&uri, 21, 14));
+        assert_eq!(
+            location["range"],
+            json!({"start": {"line": 20, "character": 1}, "end": {"line": 20, "character": 5}})
+        );
+
+        // `< ./user.json` body and `> ./check.js` handler
+        let location = session.request("textDocument/definition", at(&uri, 11, 5));
+        assert_eq!(
+            location["uri"],
+            json!(Url::from_file_path(dir.join("user.json")).unwrap())
+        );
+        let location = session.request("textDocument/definition", at(&uri, 13, 4));
+        assert_eq!(
+            location["uri"],
+            json!(Url::from_file_path(dir.join("check.js")).unwrap())
+        );
+
+        // variable of the env file
+        let location = session.request("textDocument/definition", at(&uri, 17, 18));
+        assert_eq!(
+            location["uri"],
+            json!(Url::from_file_path(dir.join(PUBLIC_ENV_FILE)).unwrap())
+        );
+        assert_eq!(
+            location["range"]["start"],
+            json!({"line": 2, "character": 4})
+        );
+
+        let location = session.request("textDocument/definition", at(&uri, 2, 2));
+        assert_eq!(location, Value::Null);
+
+        session.shutdown();
+    }
+
+    #[test]
+    fn it_should_describe_headers_on_hover() {
+        let dir = test_dir("hover");
+        let uri = Url::from_file_path(dir.join("api.http")).unwrap();
+        let mut session = Session::start();
+        session.open(&uri, SOURCE);
+
+        let hover = session.request("textDocument/hover", at(&uri, 9, 3));
+        let text = hover["contents"]["value"].as_str().unwrap();
+        assert!(text.starts_with("**Content-Type**: `application/json`"));
+        assert!(text.contains("Media type of the body."));
+        assert_eq!(hover["range"]["start"], json!({"line": 9, "character": 0}));
+
+        let hover = session.request("textDocument/hover", at(&uri, 4, 2));
+        assert_eq!(hover, Value::Null);
+
+        session.shutdown();
+    }
+
+    #[test]
+    fn it_should_keep_serving_after_invalid_messages() {
+        let mut session = Session::start();
+        session.notify("textDocument/didOpen", json!({ "textDocument": 1 }));
+
+        let error = session.request_error(
+            "workspace/executeCommand",
+            json!({ "command": "foo", "arguments": [] }),
+        );
+        assert_eq!(error.message, "unknown command foo");
+        let error = session.request_error(
+            "workspace/executeCommand",
+            json!({ "command": RUN_COMMAND, "arguments": ["untitled:api.http", 1] }),
+        );
+        assert_eq!(error.message, "untitled:api.http is not a file");
+
+        // variables of the document are found without a directory
+        let uri = Url::parse("untitled:api.http").unwrap();
+        session.open(&uri, SOURCE);
+        let location = session.request("textDocument/definition", at(&uri, 4, 14));
+        assert_eq!(location["uri"], json!(uri));
+
+        session.shutdown();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn it_should_run_request_of_saved_document() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = test_dir("run");
+        let path = dir.join("api.http");
+        fs::write(&path, SOURCE).unwrap();
+        // the cli prints its arguments
+        let restman = dir.join("restman");
+        fs::write(&restman, "#!/bin/sh\necho \"$@\"\n").unwrap();
+        fs::set_permissions(&restman, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let uri = Url::from_file_path(&path).unwrap();
+        let mut session = Session::start_with(json!({ "restmanPath": restman }));
+        session.open(&uri, SOURCE);
+        let run = json!({ "command": RUN_COMMAND, "arguments": [uri, 2] });
+
+        let output = session.request("workspace/executeCommand", run.clone());
+        assert_eq!(output, format!("run --index 2 {}\n", path.display()));
+
+        // the index of an unsaved document may point to another request
+        session.notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": uri, "version": 2 },
+                "contentChanges": [{ "text": format!("GET /a\n\n{}", SOURCE) }]
+            }),
+        );
+        session.receive_notification();
+        let error = session.request_error("workspace/executeCommand", run);
+        assert_eq!(
+            error.message,
+            format!("{} has unsaved changes, save it to run", path.display())
+        );
+
+        session.shutdown();
+    }
+}
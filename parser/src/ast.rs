@@ -1,17 +1,110 @@
-use crate::parsers::Span;
+use crate::cst::SyntaxNode;
+use crate::error::ParseError;
+use crate::parsers::{parse_template, Span};
+use std::fmt;
+use std::time::Duration;
 
 #[derive(PartialEq, Debug)]
 pub struct Request<'a> {
+    /// empty span at the first line of the request, i.e. its first comment,
+    /// directive, title, pre-request script or the request line
+    pub start: Span<'a>,
     pub method: Method,
-    pub target: String,
+    /// request line without the line ending
+    pub line: Span<'a>,
+    pub target: Template<'a>,
     pub version: Version,
     pub headers: Vec<Header<'a>>,
     pub body: MessageBody<'a>,
     pub title: Option<Span<'a>>,
+    /// `< {% script %}` or `< path` run before the request is built
+    pub pre_script: ScriptHandler<'a>,
+    /// response handler
     pub script: ScriptHandler<'a>,
+    /// `>> path` or `>>! path` to save the response body
+    pub redirect: Option<ResponseRedirect<'a>>,
+    /// `<> path` lines after the request
+    pub response_refs: Vec<ResponseRef<'a>>,
+    /// comments before the request line and between headers
+    pub comments: Vec<Comment<'a>>,
+    /// `# @name` like directives before the request line
+    pub directives: Vec<Directive<'a>>,
+}
+
+/// Name and position of a request, shared by the borrowed and the owned
+/// requests so both are selected the same way.
+pub trait RequestInfo {
+    /// title text
+    fn title(&self) -> Option<&str>;
+
+    /// line of the first comment, directive, title, pre-request script or
+    /// request line of the request
+    fn start_line(&self) -> u32;
+
+    /// value of the first `# @name` directive
+    fn name_directive(&self) -> Option<&str>;
+
+    // value of `# @name` directive if given, otherwise title
+    fn name(&self) -> Option<&str> {
+        self.name_directive()
+            .or_else(|| self.title())
+            .map(str::trim)
+    }
+}
+
+impl<'a> RequestInfo for Request<'a> {
+    fn title(&self) -> Option<&str> {
+        self.title.map(|x| *x.fragment())
+    }
+
+    fn start_line(&self) -> u32 {
+        self.start.location_line()
+    }
+
+    fn name_directive(&self) -> Option<&str> {
+        self.directives.iter().find_map(|d| match d.kind {
+            DirectiveKind::Name(x) => Some(*x.fragment()),
+            _ => None,
+        })
+    }
+}
+
+impl<'a> Request<'a> {
+    // offset of the first line of the request
+    pub fn start_offset(&self) -> usize {
+        self.start.location_offset()
+    }
+
+    // whether the version is written on the request line, it is HTTP/1.1 if not
+    pub fn has_version(&self) -> bool {
+        let end = self.target.span.location_offset() + self.target.span.len();
+        let start = self.line.location_offset();
+        !self.line.fragment()[end - start..].trim().is_empty()
+    }
+
+    pub fn has_directive(&self, kind: &DirectiveKind) -> bool {
+        self.directives.iter().any(|d| &d.kind == kind)
+    }
+
+    // structured target, `resolved` is the target with its placeholders resolved
+    pub fn target(&self, resolved: &str) -> Result<Target, TargetError> {
+        Target::parse(&self.method, resolved)
+    }
+
+    // variables used in target, headers and body in order of appearance
+    pub fn variables(&self) -> Vec<&Span<'a>> {
+        let mut variables: Vec<&Span<'a>> = self.target.variables().collect();
+        for h in self.headers.iter() {
+            variables.extend(h.value.variables());
+        }
+        variables.extend(self.body.variables());
+
+        variables
+    }
 }
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Method {
     Get,
     Post,
@@ -20,29 +113,136 @@ pub enum Method {
     Put,
     Patch,
     Delete,
+    Connect,
     Custom(String),
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(Debug)]
 pub enum MessageBody<'a> {
-    Bytes(Span<'a>),
+    Bytes(Template<'a>),
     Empty,
     File(Span<'a>),
+    /// `multipart/*` body split on the boundary of the Content-Type header
+    Multipart {
+        /// whole body including the delimiters
+        span: Span<'a>,
+        boundary: Span<'a>,
+        parts: Vec<Part<'a>>,
+    },
+    /// inline text mixed with `< path` lines
+    Parts {
+        span: Span<'a>,
+        parts: Vec<BodyPart<'a>>,
+    },
+}
+
+#[derive(Debug)]
+pub enum BodyPart<'a> {
+    Inline(Template<'a>),
+    /// path of the file relative to the .http file
+    File(Span<'a>),
+}
+
+impl<'a> PartialEq for BodyPart<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (BodyPart::Inline(x), BodyPart::Inline(y)) => x == y,
+            (BodyPart::File(x), BodyPart::File(y)) => x.fragment() == y.fragment(),
+            _ => false,
+        }
+    }
+}
+
+/// One part of a multipart body. Body of the part is never `Multipart`.
+#[derive(PartialEq, Debug)]
+pub struct Part<'a> {
+    pub headers: Vec<Header<'a>>,
+    pub body: MessageBody<'a>,
+}
+
+impl<'a> PartialEq for MessageBody<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (MessageBody::Bytes(x), MessageBody::Bytes(y)) => x == y,
+            (MessageBody::Empty, MessageBody::Empty) => true,
+            (MessageBody::File(x), MessageBody::File(y)) => x.fragment() == y.fragment(),
+            (
+                MessageBody::Multipart {
+                    boundary: x,
+                    parts: x_parts,
+                    ..
+                },
+                MessageBody::Multipart {
+                    boundary: y,
+                    parts: y_parts,
+                    ..
+                },
+            ) => x.fragment() == y.fragment() && x_parts == y_parts,
+            (MessageBody::Parts { parts: x, .. }, MessageBody::Parts { parts: y, .. }) => x == y,
+            _ => false,
+        }
+    }
 }
 
 impl<'a> MessageBody<'a> {
-    pub fn get_span(&'a self) -> Option<&'a Span> {
+    pub fn get_span(&'a self) -> Option<&'a Span<'a>> {
         match self {
-            MessageBody::Bytes(x) => Some(x),
+            MessageBody::Bytes(x) => Some(&x.span),
             MessageBody::Empty => None,
             MessageBody::File(x) => Some(x),
+            MessageBody::Multipart { span, .. } => Some(span),
+            MessageBody::Parts { span, .. } => Some(span),
+        }
+    }
+
+    // variables used in inline content and part headers
+    pub fn variables(&self) -> Vec<&Span<'a>> {
+        match self {
+            MessageBody::Bytes(x) => x.variables().collect(),
+            MessageBody::Empty | MessageBody::File(_) => vec![],
+            MessageBody::Multipart { parts, .. } => parts
+                .iter()
+                .flat_map(|p| {
+                    let headers = p.headers.iter().flat_map(|h| h.value.variables());
+                    headers.chain(p.body.variables())
+                })
+                .collect(),
+            MessageBody::Parts { parts, .. } => parts
+                .iter()
+                .flat_map(|p| match p {
+                    BodyPart::Inline(x) => x.variables().collect(),
+                    BodyPart::File(_) => vec![],
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Method {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Method::Get => "GET",
+            Method::Post => "POST",
+            Method::Head => "HEAD",
+            Method::Options => "OPTIONS",
+            Method::Put => "PUT",
+            Method::Patch => "PATCH",
+            Method::Delete => "DELETE",
+            Method::Connect => "CONNECT",
+            Method::Custom(x) => x,
         }
     }
 }
 
 impl From<Span<'_>> for Method {
     fn from(i: Span) -> Self {
-        match i.fragment().as_bytes() {
+        Method::from(*i.fragment())
+    }
+}
+
+impl From<&str> for Method {
+    fn from(i: &str) -> Self {
+        match i.as_bytes() {
             b"GET" => Method::Get,
             b"POST" => Method::Post,
             b"HEAD" => Method::Head,
@@ -50,27 +250,254 @@ impl From<Span<'_>> for Method {
             b"PUT" => Method::Put,
             b"PATCH" => Method::Patch,
             b"DELETE" => Method::Delete,
+            b"CONNECT" => Method::Connect,
             x => Method::Custom(String::from_utf8_lossy(x).to_string()),
         }
     }
 }
 
+/// Form of the request target, see RFC 9112 section 3.2.
 #[derive(PartialEq, Debug, Clone, Copy)]
+pub enum TargetForm {
+    /// `/index.html?x=1`
+    Origin,
+    /// `https://example.com/index.html`, scheme can be omitted in .http files
+    Absolute,
+    /// `example.com:443`, only for CONNECT
+    Authority,
+    /// `*`, only for OPTIONS
+    Asterisk,
+}
+
+impl TargetForm {
+    // form of the target text for the method.
+    // returns None if the target can not be used with the method.
+    pub fn detect(method: &Method, target: &str) -> Option<TargetForm> {
+        if *method == Method::Connect {
+            return (!target.contains('/')).then_some(TargetForm::Authority);
+        }
+
+        if target == "*" {
+            (*method == Method::Options).then_some(TargetForm::Asterisk)
+        } else if target.starts_with('/') {
+            Some(TargetForm::Origin)
+        } else {
+            Some(TargetForm::Absolute)
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum TargetError {
+    InvalidForm(String),
+    InvalidScheme(String),
+    InvalidPort(String),
+    MissingHost(String),
+}
+
+impl fmt::Display for TargetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TargetError::InvalidForm(x) => write!(f, "target is not valid for the method: '{}'", x),
+            TargetError::InvalidScheme(x) => write!(f, "invalid scheme: '{}'", x),
+            TargetError::InvalidPort(x) => write!(f, "invalid port: '{}'", x),
+            TargetError::MissingHost(x) => write!(f, "target has no host: '{}'", x),
+        }
+    }
+}
+
+impl std::error::Error for TargetError {}
+
+/// Query parameter, name and value are not percent-decoded.
+#[derive(PartialEq, Debug, Clone)]
+pub struct QueryParam {
+    pub name: String,
+    pub value: Option<String>,
+}
+
+/// Request target split into its components.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Target {
+    pub form: TargetForm,
+    /// lowercase scheme of absolute-form targets if given
+    pub scheme: Option<String>,
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    /// `*` for asterisk-form, empty for authority-form
+    pub path: String,
+    /// query string without `?`
+    pub query: Option<String>,
+    pub params: Vec<QueryParam>,
+}
+
+impl Target {
+    // parse resolved target text
+    pub fn parse(method: &Method, target: &str) -> Result<Target, TargetError> {
+        let form = TargetForm::detect(method, target)
+            .ok_or_else(|| TargetError::InvalidForm(target.to_string()))?;
+
+        let mut t = Target {
+            form,
+            scheme: None,
+            host: None,
+            port: None,
+            path: String::new(),
+            query: None,
+            params: vec![],
+        };
+
+        let rest = match form {
+            TargetForm::Asterisk => {
+                t.path = "*".to_string();
+                return Ok(t);
+            }
+            TargetForm::Authority => {
+                (t.host, t.port) = parse_authority(target)?;
+                if t.port.is_none() {
+                    return Err(TargetError::InvalidPort(target.to_string()));
+                }
+                return Ok(t);
+            }
+            TargetForm::Origin => target,
+            TargetForm::Absolute => {
+                let rest = match target.split_once("://") {
+                    Some((scheme, rest)) => {
+                        let valid = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                            && scheme
+                                .chars()
+                                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c));
+                        if !valid {
+                            return Err(TargetError::InvalidScheme(scheme.to_string()));
+                        }
+                        t.scheme = Some(scheme.to_ascii_lowercase());
+                        rest
+                    }
+                    None => target,
+                };
+
+                let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+                (t.host, t.port) = parse_authority(&rest[..end])?;
+                &rest[end..]
+            }
+        };
+
+        // fragment is not part of the request target
+        let rest = rest.split('#').next().unwrap_or_default();
+        let (path, query) = match rest.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (rest, None),
+        };
+
+        t.path = if path.is_empty() { "/" } else { path }.to_string();
+        if let Some(query) = query {
+            t.params = query
+                .split('&')
+                .filter(|x| !x.is_empty())
+                .map(|x| match x.split_once('=') {
+                    Some((name, value)) => QueryParam {
+                        name: name.to_string(),
+                        value: Some(value.to_string()),
+                    },
+                    None => QueryParam {
+                        name: x.to_string(),
+                        value: None,
+                    },
+                })
+                .collect();
+            t.query = Some(query.to_string());
+        }
+
+        Ok(t)
+    }
+
+    // origin-form of the target to use in the request line
+    pub fn path_and_query(&self) -> String {
+        match &self.query {
+            Some(q) => format!("{}?{}", self.path, q),
+            None => self.path.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.form == TargetForm::Asterisk {
+            return write!(f, "*");
+        }
+        if let Some(scheme) = &self.scheme {
+            write!(f, "{}://", scheme)?;
+        }
+        if let Some(host) = &self.host {
+            write!(f, "{}", host)?;
+        }
+        if let Some(port) = self.port {
+            write!(f, ":{}", port)?;
+        }
+        match self.form {
+            TargetForm::Authority => Ok(()),
+            _ => write!(f, "{}", self.path_and_query()),
+        }
+    }
+}
+
+// `[userinfo@]host[:port]`, returns host and port
+pub fn parse_authority(i: &str) -> Result<(Option<String>, Option<u16>), TargetError> {
+    let i = i.rsplit_once('@').map(|(_, x)| x).unwrap_or(i);
+
+    // ipv6 address is in brackets
+    let port_sep = match i.rfind(']') {
+        Some(end) => i[end..].find(':').map(|x| x + end),
+        None => i.rfind(':'),
+    };
+
+    let (host, port) = match port_sep {
+        Some(pos) => {
+            let port = &i[pos + 1..];
+            let port = port
+                .parse()
+                .map_err(|_| TargetError::InvalidPort(port.to_string()))?;
+            (&i[..pos], Some(port))
+        }
+        None => (i, None),
+    };
+
+    if host.is_empty() {
+        return Err(TargetError::MissingHost(i.to_string()));
+    }
+
+    Ok((Some(host.to_string()), port))
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Version {
     V10,
     V11,
+    V2,
+    V3,
+}
+
+impl Version {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Version::V10 => "HTTP/1.0",
+            Version::V11 => "HTTP/1.1",
+            Version::V2 => "HTTP/2",
+            Version::V3 => "HTTP/3",
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Header<'a> {
     pub name: Span<'a>,
-    pub value: Span<'a>,
+    pub value: Template<'a>,
 }
 
+#[allow(clippy::partialeq_ne_impl)]
 impl<'a> PartialEq for Header<'a> {
     fn eq(&self, other: &Self) -> bool {
-        self.name.fragment() == other.name.fragment()
-            && self.value.fragment() == other.value.fragment()
+        self.name.fragment() == other.name.fragment() && self.value == other.value
     }
 
     fn ne(&self, other: &Self) -> bool {
@@ -78,6 +505,227 @@ impl<'a> PartialEq for Header<'a> {
     }
 }
 
+/// Root of a parsed .http file. Errors of the broken requests are kept in
+/// the file with the input they skipped.
+///
+/// Fields can be added without a breaking change, destructure it with `..`.
+#[derive(PartialEq, Debug)]
+#[non_exhaustive]
+pub struct HttpFile<'a> {
+    pub filename: &'a str,
+    /// `@name = value` lines in source order
+    pub variables: Vec<VariableDeclaration<'a>>,
+    pub requests: Vec<Request<'a>>,
+    /// comments that do not belong to a request, e.g. at the end of the file
+    pub comments: Vec<Comment<'a>>,
+    /// input skipped because it could not be parsed
+    pub skipped: Vec<Span<'a>>,
+    /// errors of the skipped input
+    pub diagnostics: Vec<ParseError>,
+    /// entries of the lists above in source order
+    pub(crate) items: Vec<ItemIndex>,
+    pub(crate) tree: SyntaxNode<'a>,
+}
+
+// position of an item in its list of the file
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub(crate) enum ItemIndex {
+    Variable(usize),
+    Request(usize),
+    Comment(usize),
+    Skipped(usize),
+}
+
+/// An entry of a .http file, see [HttpFile::items].
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Item<'f, 'a> {
+    Variable(&'f VariableDeclaration<'a>),
+    Request(&'f Request<'a>),
+    Comment(&'f Comment<'a>),
+    Skipped(Span<'a>),
+}
+
+impl<'f, 'a> Item<'f, 'a> {
+    // offset of the first byte of the item in the file
+    pub fn offset(&self) -> usize {
+        match self {
+            Item::Variable(x) => x.span.location_offset(),
+            Item::Request(x) => x.start_offset(),
+            Item::Comment(x) => x.span.location_offset(),
+            Item::Skipped(x) => x.location_offset(),
+        }
+    }
+}
+
+impl<'a> HttpFile<'a> {
+    pub(crate) fn new(tree: SyntaxNode<'a>) -> Self {
+        HttpFile {
+            filename: tree.span.extra,
+            variables: vec![],
+            requests: vec![],
+            comments: vec![],
+            skipped: vec![],
+            diagnostics: vec![],
+            items: vec![],
+            tree,
+        }
+    }
+
+    // all entries of the file in source order. variables declared inside of
+    // a request come before the request, they are set before it runs.
+    pub fn items(&self) -> Vec<Item<'_, 'a>> {
+        self.items
+            .iter()
+            .map(|x| match *x {
+                ItemIndex::Variable(i) => Item::Variable(&self.variables[i]),
+                ItemIndex::Request(i) => Item::Request(&self.requests[i]),
+                ItemIndex::Comment(i) => Item::Comment(&self.comments[i]),
+                ItemIndex::Skipped(i) => Item::Skipped(self.skipped[i]),
+            })
+            .collect()
+    }
+
+    // the push functions below must be called in source order
+
+    pub(crate) fn push_variable(&mut self, x: VariableDeclaration<'a>) {
+        self.items.push(ItemIndex::Variable(self.variables.len()));
+        self.variables.push(x);
+    }
+
+    pub(crate) fn push_request(&mut self, x: Request<'a>) {
+        self.items.push(ItemIndex::Request(self.requests.len()));
+        self.requests.push(x);
+    }
+
+    pub(crate) fn push_comment(&mut self, x: Comment<'a>) {
+        self.items.push(ItemIndex::Comment(self.comments.len()));
+        self.comments.push(x);
+    }
+
+    pub(crate) fn push_skipped(&mut self, x: Span<'a>, error: ParseError) {
+        self.items.push(ItemIndex::Skipped(self.skipped.len()));
+        self.skipped.push(x);
+        self.diagnostics.push(error);
+    }
+}
+
+/// A `@name = value` line. Value can reference the variables declared before it.
+#[derive(Debug)]
+pub struct VariableDeclaration<'a> {
+    /// whole line without the line ending
+    pub span: Span<'a>,
+    pub name: Span<'a>,
+    pub value: Template<'a>,
+}
+
+impl<'a> PartialEq for VariableDeclaration<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.name.fragment() == other.name.fragment() && self.value == other.value
+    }
+}
+
+/// A `>> path` line that saves the response body to the file. If the file
+/// exists it is saved with a new name unless it is given as `>>! path`.
+#[derive(Debug, Clone)]
+pub struct ResponseRedirect<'a> {
+    /// path of the output file relative to the .http file
+    pub path: Span<'a>,
+    pub overwrite: bool,
+}
+
+impl<'a> PartialEq for ResponseRedirect<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.path.fragment() == other.path.fragment() && self.overwrite == other.overwrite
+    }
+}
+
+/// A `<> path` line that links a saved response of the request.
+#[derive(Debug, Clone)]
+pub struct ResponseRef<'a> {
+    /// path of the saved response relative to the .http file
+    pub path: Span<'a>,
+}
+
+impl<'a> PartialEq for ResponseRef<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.path.fragment() == other.path.fragment()
+    }
+}
+
+/// A `# text` or `// text` line.
+#[derive(Debug, Clone)]
+pub struct Comment<'a> {
+    /// whole comment including the marker
+    pub span: Span<'a>,
+    /// text after the marker
+    pub text: Span<'a>,
+}
+
+impl<'a> PartialEq for Comment<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.span.fragment() == other.span.fragment()
+    }
+}
+
+/// A `# @directive [value]` comment that configures the request.
+#[derive(Debug, Clone)]
+pub struct Directive<'a> {
+    /// whole directive including the comment marker
+    pub span: Span<'a>,
+    pub kind: DirectiveKind<'a>,
+}
+
+#[derive(Debug, Clone)]
+pub enum DirectiveKind<'a> {
+    /// `@name login`
+    Name(Span<'a>),
+    /// `@no-redirect`
+    NoRedirect,
+    /// `@no-log`
+    NoLog,
+    /// `@no-cookie-jar`
+    NoCookieJar,
+    /// `@timeout 30`, response timeout
+    Timeout(Duration),
+    /// `@connection-timeout 2 m`
+    ConnectionTimeout(Duration),
+    /// unknown directive or known directive with an invalid value
+    Other {
+        name: Span<'a>,
+        value: Option<Span<'a>>,
+    },
+}
+
+impl<'a> PartialEq for Directive<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.span.fragment() == other.span.fragment() && self.kind == other.kind
+    }
+}
+
+impl<'a> PartialEq for DirectiveKind<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Name(x), Self::Name(y)) => x.fragment() == y.fragment(),
+            (Self::NoRedirect, Self::NoRedirect) => true,
+            (Self::NoLog, Self::NoLog) => true,
+            (Self::NoCookieJar, Self::NoCookieJar) => true,
+            (Self::Timeout(x), Self::Timeout(y)) => x == y,
+            (Self::ConnectionTimeout(x), Self::ConnectionTimeout(y)) => x == y,
+            (
+                Self::Other { name, value },
+                Self::Other {
+                    name: other_name,
+                    value: other_value,
+                },
+            ) => {
+                name.fragment() == other_name.fragment()
+                    && value.map(|x| *x.fragment()) == other_value.map(|x| *x.fragment())
+            }
+            (_, _) => false,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum ScriptHandler<'a> {
     File(Span<'a>),
@@ -85,6 +733,7 @@ pub enum ScriptHandler<'a> {
     Empty,
 }
 
+#[allow(clippy::partialeq_ne_impl)]
 impl<'a> PartialEq for ScriptHandler<'a> {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
@@ -99,3 +748,87 @@ impl<'a> PartialEq for ScriptHandler<'a> {
         !self.eq(other)
     }
 }
+
+/// Text that may contain `{{variable}}` placeholders.
+#[derive(Debug, Clone)]
+pub struct Template<'a> {
+    pub span: Span<'a>,
+    pub segments: Vec<Segment<'a>>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Segment<'a> {
+    Literal(Span<'a>),
+    /// name of the variable without braces and surrounding spaces
+    Variable(Span<'a>),
+}
+
+impl<'a> Template<'a> {
+    pub fn variables(&self) -> impl Iterator<Item = &Span<'a>> {
+        self.segments.iter().filter_map(|s| match s {
+            Segment::Variable(name) => Some(name),
+            Segment::Literal(_) => None,
+        })
+    }
+
+    // replace placeholders with the values returned by resolve.
+    // placeholders that can not be resolved are kept as is.
+    pub fn render<F>(&self, mut resolve: F) -> String
+    where
+        F: FnMut(&str) -> Option<String>,
+    {
+        let mut out = String::with_capacity(self.span.len());
+        for s in self.segments.iter() {
+            match s {
+                Segment::Literal(x) => out.push_str(x.fragment()),
+                Segment::Variable(name) => match resolve(name.fragment()) {
+                    Some(value) => out.push_str(&value),
+                    None => {
+                        out.push_str("{{");
+                        out.push_str(name.fragment());
+                        out.push_str("}}");
+                    }
+                },
+            }
+        }
+
+        out
+    }
+}
+
+// text of the template, placeholders are written as `{{name}}`
+impl<'a> fmt::Display for Template<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for s in self.segments.iter() {
+            match s {
+                Segment::Literal(x) => f.write_str(x.fragment())?,
+                Segment::Variable(name) => write!(f, "{{{{{}}}}}", name.fragment())?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> From<Span<'a>> for Template<'a> {
+    fn from(i: Span<'a>) -> Self {
+        // template parser consumes any input
+        parse_template(i).unwrap().1
+    }
+}
+
+impl<'a> PartialEq for Template<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.span.fragment() == other.span.fragment() && self.segments == other.segments
+    }
+}
+
+impl<'a> PartialEq for Segment<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Literal(x), Self::Literal(y)) => x.fragment() == y.fragment(),
+            (Self::Variable(x), Self::Variable(y)) => x.fragment() == y.fragment(),
+            (_, _) => false,
+        }
+    }
+}
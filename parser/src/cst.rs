@@ -0,0 +1,488 @@
+//! Lossless syntax tree of a .http file.
+//!
+//! The parser builds the tree first and the AST is derived from its nodes.
+//! Every byte of the source belongs to exactly one leaf of the tree. Blank
+//! lines and line endings that are not part of a line of the grammar are
+//! kept as `Whitespace` leaves, so writing the leaves in order gives the
+//! source back.
+
+use crate::ast::HttpFile;
+use crate::parsers::{
+    self, parse_comment, parse_directive, parse_header, parse_pre_request_script,
+    parse_request_title, parse_response_redirect, parse_script, parse_variable_declaration,
+    request_line, IResult, Lowered, Span,
+};
+use crate::scanners::*;
+use nom::bytes::complete::take;
+use nom::character::complete::line_ending;
+use nom::combinator::opt;
+use nom::error::context;
+use nom::multi::many0_count;
+use nom::Slice;
+use std::fmt;
+use std::ops::Range;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum SyntaxKind {
+    File,
+    /// lines from the first comment or title of a request to its last line
+    /// and the blank lines after it
+    Request,
+    /// `###` line. a title that is not followed by a request, e.g. at the
+    /// end of the file, is a top-level node
+    Title,
+    Comment,
+    Directive,
+    Variable,
+    PreScript,
+    /// request line including the indented continuation lines
+    RequestLine,
+    Header,
+    Body,
+    Script,
+    Redirect,
+    ResponseRef,
+    /// input skipped because it could not be parsed
+    Skipped,
+    /// blank lines and line endings
+    Whitespace,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct SyntaxNode<'a> {
+    pub kind: SyntaxKind,
+    /// whole text of the node including its line ending
+    pub span: Span<'a>,
+    pub children: Vec<SyntaxNode<'a>>,
+}
+
+/// A change of the source, bytes `start..old_end` of the old source are
+/// replaced with the bytes `start..new_end` of the new source.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Edit {
+    pub start: usize,
+    pub old_end: usize,
+    pub new_end: usize,
+}
+
+impl<'a> SyntaxNode<'a> {
+    fn leaf(kind: SyntaxKind, span: Span<'a>) -> Self {
+        SyntaxNode {
+            kind,
+            span,
+            children: vec![],
+        }
+    }
+
+    // byte range of the node in the source
+    pub fn range(&self) -> Range<usize> {
+        let start = self.span.location_offset();
+        start..start + self.span.len()
+    }
+
+    // nodes without children in source order
+    pub fn leaves(&self) -> Vec<&SyntaxNode<'a>> {
+        match self.children.is_empty() {
+            true => vec![self],
+            false => self.children.iter().flat_map(|x| x.leaves()).collect(),
+        }
+    }
+
+    // innermost node that contains the offset
+    pub fn node_at(&self, offset: usize) -> Option<&SyntaxNode<'a>> {
+        if !self.range().contains(&offset) {
+            return None;
+        }
+
+        self.children
+            .iter()
+            .find_map(|x| x.node_at(offset))
+            .or(Some(self))
+    }
+
+    // same node over the input that starts with its text
+    fn rebase<'b>(&self, i: Span<'b>) -> SyntaxNode<'b> {
+        let mut rest = i;
+        let children = self
+            .children
+            .iter()
+            .map(|x| {
+                let node = x.rebase(rest);
+                rest = rest.slice(x.span.len()..);
+                node
+            })
+            .collect();
+
+        SyntaxNode {
+            kind: self.kind,
+            span: i.slice(..self.span.len()),
+            children,
+        }
+    }
+}
+
+// source text of the node
+impl<'a> fmt::Display for SyntaxNode<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.leaves()
+            .iter()
+            .try_for_each(|x| f.write_str(x.span.fragment()))
+    }
+}
+
+impl<'a> HttpFile<'a> {
+    // syntax tree the file is derived from
+    pub fn syntax_tree(&self) -> &SyntaxNode<'a> {
+        &self.tree
+    }
+
+    // file of the edited source. only the items around the edit are parsed
+    // again, the nodes before and after it are moved to their new offsets.
+    pub fn reparse<'b>(&self, source: &'b str, edit: &Edit) -> HttpFile<'b>
+    where
+        'a: 'b,
+    {
+        let root = Span::new_extra(source, self.filename);
+        let (tree, lowered) = reparse(&self.tree, root, edit);
+        parsers::lower_file(tree, lowered)
+    }
+}
+
+// nodes of a parent in source order
+#[derive(Default)]
+struct Nodes<'a> {
+    nodes: Vec<SyntaxNode<'a>>,
+    // input at the start of each node, the span of the parent is sliced from it
+    inputs: Vec<Span<'a>>,
+    // start of the whitespace that is not pushed yet
+    space: Option<Span<'a>>,
+}
+
+impl<'a> Nodes<'a> {
+    // text from `from` to `to` as a leaf. blank lines after the line ending
+    // of a line are whitespace, the body and skipped input keep them.
+    fn push(&mut self, kind: SyntaxKind, from: Span<'a>, to: Span<'a>) {
+        let len = to.location_offset() - from.location_offset();
+        if len == 0 {
+            return;
+        }
+        if kind == SyntaxKind::Whitespace {
+            self.space.get_or_insert(from);
+            return;
+        }
+
+        self.flush(from);
+        let end = match kind {
+            SyntaxKind::Body | SyntaxKind::Skipped => len,
+            _ => line_end(&from.fragment()[..len]),
+        };
+        self.nodes.push(SyntaxNode::leaf(kind, from.slice(..end)));
+        self.inputs.push(from);
+        if end < len {
+            self.space = Some(from.slice(end..));
+        }
+    }
+
+    // push the pending whitespace that ends at `to`
+    fn flush(&mut self, to: Span<'a>) {
+        if let Some(start) = self.space.take() {
+            let len = to.location_offset() - start.location_offset();
+            self.nodes
+                .push(SyntaxNode::leaf(SyntaxKind::Whitespace, start.slice(..len)));
+            self.inputs.push(start);
+        }
+    }
+
+    // run the parser and push what it consumed
+    fn parse<O>(
+        &mut self,
+        kind: SyntaxKind,
+        mut parser: impl FnMut(Span<'a>) -> IResult<'a, O>,
+        i: Span<'a>,
+    ) -> IResult<'a, O> {
+        let (j, x) = parser(i)?;
+        self.push(kind, i, j);
+        Ok((j, x))
+    }
+
+    // `many0(newline)` of the grammar
+    fn newlines(&mut self, i: Span<'a>) -> Span<'a> {
+        let (j, _) = many0_count(line_ending::<_, ()>)(i).unwrap();
+        self.push(SyntaxKind::Whitespace, i, j);
+        j
+    }
+
+    // comment lines, and variable declarations if `variables` is set, with
+    // blank lines between them. comments can be directives if `directives`
+    // is set.
+    fn comments(&mut self, mut i: Span<'a>, variables: bool, directives: bool) -> Span<'a> {
+        loop {
+            let j = if let Ok((j, c)) = parse_comment(i) {
+                let kind = match directives && parse_directive(&c).is_some() {
+                    true => SyntaxKind::Directive,
+                    false => SyntaxKind::Comment,
+                };
+                self.push(kind, i, j);
+                j
+            } else if let (true, Ok((j, _))) = (variables, parse_variable_declaration(i)) {
+                self.push(SyntaxKind::Variable, i, j);
+                j
+            } else {
+                return i;
+            };
+            i = self.newlines(j);
+        }
+    }
+
+    // headers and comment lines between them
+    fn headers(&mut self, mut i: Span<'a>) -> Span<'a> {
+        loop {
+            let j = if let Ok((j, _)) = parse_comment(i) {
+                self.push(SyntaxKind::Comment, i, j);
+                j
+            } else if let Ok((j, _)) = parse_header(i) {
+                self.push(SyntaxKind::Header, i, j);
+                j
+            } else {
+                return i;
+            };
+            i = j;
+        }
+    }
+
+    // `<> path` lines with the blank lines after them
+    fn response_refs(&mut self, mut i: Span<'a>) -> Span<'a> {
+        while let Ok((j, _)) = response_ref(i) {
+            self.push(SyntaxKind::ResponseRef, i, j);
+            i = self.newlines(j);
+        }
+        i
+    }
+
+    fn finish(mut self, to: Span<'a>) -> Vec<SyntaxNode<'a>> {
+        self.flush(to);
+        self.nodes
+    }
+}
+
+// end of the line ending after the text of a line
+fn line_end(s: &str) -> usize {
+    let text = s.trim_end_matches(['\r', '\n']).len();
+    match &s[text..] {
+        x if x.starts_with("\r\n") => text + 2,
+        x if x.starts_with(['\r', '\n']) => text + 1,
+        _ => text,
+    }
+}
+
+// nodes of the next request. whitespace and variables before the first
+// comment, title or request line are top-level nodes, the last node is the
+// request.
+pub(crate) fn request(i: Span) -> IResult<Vec<SyntaxNode>> {
+    let mut x = Nodes::default();
+
+    let i = x.newlines(i);
+    let i = x.comments(i, true, true);
+    let (i, _) = x.parse(SyntaxKind::Title, parse_request_title, i)?;
+    let i = x.newlines(i);
+    let i = x.comments(i, true, true);
+    let (i, _) = x.parse(SyntaxKind::PreScript, parse_pre_request_script, i)?;
+    let i = x.comments(i, false, true);
+    let (i, _) = x.parse(
+        SyntaxKind::RequestLine,
+        context("request line", request_line),
+        i,
+    )?;
+    // allowing to only 1 newline
+    let (i, _) = x.parse(SyntaxKind::Whitespace, opt(line_ending), i)?;
+    let i = x.headers(i);
+    let i = x.newlines(i);
+    let (i, _) = x.parse(SyntaxKind::Body, take(parsers::body_len(i)), i)?;
+    let i = x.response_refs(i);
+    let (i, redirect) = x.parse(SyntaxKind::Redirect, opt(parse_response_redirect), i)?;
+    let (i, _) = x.parse(SyntaxKind::Script, parse_script, i)?;
+    let i = x.newlines(i);
+    let i = match redirect {
+        Some(_) => i,
+        None => {
+            x.parse(SyntaxKind::Redirect, opt(parse_response_redirect), i)?
+                .0
+        }
+    };
+    let i = x.response_refs(i);
+
+    x.flush(i);
+    let start = x
+        .nodes
+        .iter()
+        .position(|n| !matches!(n.kind, SyntaxKind::Whitespace | SyntaxKind::Variable))
+        .unwrap_or(x.nodes.len());
+    let input = x.inputs.get(start).copied().unwrap_or(i);
+    let children = x.nodes.split_off(start);
+    x.nodes.push(SyntaxNode {
+        kind: SyntaxKind::Request,
+        span: input.slice(..i.location_offset() - input.location_offset()),
+        children,
+    });
+
+    Ok((i, x.nodes))
+}
+
+// title, declarations and comments at the end of the file are not followed
+// by a request
+fn trailing(i: Span) -> Option<Vec<SyntaxNode>> {
+    let mut x = Nodes::default();
+    let (i, _) = x.parse(SyntaxKind::Title, parse_request_title, i).ok()?;
+    let i = x.comments(i, true, false);
+
+    match i.is_empty() {
+        true => Some(x.finish(i)),
+        false => None,
+    }
+}
+
+// request of a `Request` node or the error of a `Skipped` node that is
+// reused from an old tree, `i` is the input from the start of the node on
+fn lower<'a>(node: &SyntaxNode<'a>, i: Span<'a>) -> Lowered<'a> {
+    match node.kind {
+        SyntaxKind::Request => parsers::lower_request(node),
+        _ => request(i).and_then(|(_, top)| parsers::lower_request(&top[top.len() - 1])),
+    }
+}
+
+// top-level nodes from the input on, and the result of each `Request` or
+// `Skipped` node in `lowered`. `stop` is called with the rest of the input
+// after each item, the rest is returned when it returns true.
+fn items<'a>(
+    mut i: Span<'a>,
+    nodes: &mut Vec<SyntaxNode<'a>>,
+    lowered: &mut Vec<Lowered<'a>>,
+    mut stop: impl FnMut(Span<'a>) -> bool,
+) -> Span<'a> {
+    loop {
+        let mut x = Nodes::default();
+        let j = x.newlines(i);
+        nodes.extend(x.finish(j));
+        if j.is_empty() {
+            return j;
+        }
+
+        if let Some(trailing) = trailing(j) {
+            nodes.extend(trailing);
+            return j.slice(j.len()..);
+        }
+
+        // the request must be derivable from its node, e.g. the parts of a
+        // multipart body must be valid
+        let request = request(j).and_then(|(rest, top)| {
+            let x = parsers::lower_request(&top[top.len() - 1])?;
+            Ok((rest, top, x))
+        });
+        i = match request {
+            Ok((rest, top, x)) => {
+                nodes.extend(top);
+                lowered.push(Ok(x));
+                rest
+            }
+            Err(e) => {
+                lowered.push(Err(e));
+                // skipping until the next title can not fail
+                let (rest, _) = skip_to_next_request_title(j).unwrap();
+                let mut x = Nodes::default();
+                x.push(SyntaxKind::Skipped, j, rest);
+                nodes.extend(x.finish(rest));
+                rest
+            }
+        };
+
+        if stop(i) {
+            return i;
+        }
+    }
+}
+
+// syntax tree of the whole input with its lowered requests
+pub(crate) fn file(root: Span) -> (SyntaxNode, Vec<Lowered>) {
+    let mut children = vec![];
+    let mut lowered = vec![];
+    items(root, &mut children, &mut lowered, |_| false);
+
+    let tree = SyntaxNode {
+        kind: SyntaxKind::File,
+        span: root,
+        children,
+    };
+    (tree, lowered)
+}
+
+// tree of the edited source. parsing starts again at the request before the
+// edit, an edit can merge an item into it e.g. by removing a title. it stops
+// at the first item after the edit that starts where an old item started.
+fn reparse<'a, 'b>(
+    tree: &SyntaxNode<'a>,
+    root: Span<'b>,
+    edit: &Edit,
+) -> (SyntaxNode<'b>, Vec<Lowered<'b>>) {
+    let old = &tree.children;
+    let edited = old
+        .iter()
+        .position(|x| x.range().end > edit.start)
+        .unwrap_or(old.len());
+    let first = old[..edited]
+        .iter()
+        .rposition(|x| matches!(x.kind, SyntaxKind::Request | SyntaxKind::Skipped))
+        .unwrap_or(0);
+
+    let mut children = vec![];
+    let mut lowered = vec![];
+    let mut i = root;
+    for x in old[..first].iter() {
+        i = reuse(x, i, &mut children, &mut lowered);
+    }
+
+    let mut reused = old.len();
+    let i = items(i, &mut children, &mut lowered, |j| {
+        let offset = j.location_offset();
+        if offset < edit.new_end {
+            return false;
+        }
+
+        let start = offset - edit.new_end + edit.old_end;
+        let k = old.partition_point(|x| x.range().start < start);
+        match old.get(k) {
+            Some(x) if x.range().start == start => {
+                reused = k;
+                true
+            }
+            _ => false,
+        }
+    });
+
+    let mut i = i;
+    for x in old[reused..].iter() {
+        i = reuse(x, i, &mut children, &mut lowered);
+    }
+
+    let tree = SyntaxNode {
+        kind: SyntaxKind::File,
+        span: root,
+        children,
+    };
+    (tree, lowered)
+}
+
+// moves an old node to the input and lowers it again, returns the rest of
+// the input after it
+fn reuse<'b>(
+    x: &SyntaxNode,
+    i: Span<'b>,
+    children: &mut Vec<SyntaxNode<'b>>,
+    lowered: &mut Vec<Lowered<'b>>,
+) -> Span<'b> {
+    let x = x.rebase(i);
+    if matches!(x.kind, SyntaxKind::Request | SyntaxKind::Skipped) {
+        lowered.push(lower(&x, i));
+    }
+    children.push(x);
+    i.slice(children[children.len() - 1].span.len()..)
+}
@@ -0,0 +1,96 @@
+use crate::parsers::Span;
+use nom::error::{ErrorKind, VerboseError, VerboseErrorKind};
+use std::fmt;
+
+/// A parse failure with the position of the offending input.
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParseError {
+    pub filename: String,
+    /// 1-based line number
+    pub line: u32,
+    /// 1-based column, counted in characters
+    pub column: usize,
+    /// the source line that contains the error
+    pub snippet: String,
+    /// human-readable reason, e.g. "expected request line"
+    pub message: String,
+}
+
+impl ParseError {
+    pub(crate) fn from_nom(filename: &str, source: &str, e: nom::Err<VerboseError<Span>>) -> Self {
+        match e {
+            nom::Err::Error(e) | nom::Err::Failure(e) => Self::from_verbose(source, e),
+            nom::Err::Incomplete(_) => Self::at_eof(filename, source, "unexpected end of input"),
+        }
+    }
+
+    fn from_verbose(source: &str, e: VerboseError<Span>) -> Self {
+        // errors are ordered from the innermost parser to the outermost one,
+        // so the first entry points to the exact location of the failure and
+        // the first context label is the most specific description of it.
+        let (span, _) = e.errors.first().expect("nom error without entries");
+
+        let message = match e.errors.iter().find_map(|(_, kind)| match kind {
+            VerboseErrorKind::Context(ctx) => Some(*ctx),
+            _ => None,
+        }) {
+            Some(ctx) => format!("expected {}", ctx),
+            None => describe(&e.errors[0].1),
+        };
+
+        Self::at(source, span, message)
+    }
+
+    fn at(source: &str, span: &Span, message: String) -> Self {
+        ParseError {
+            filename: span.extra.to_string(),
+            line: span.location_line(),
+            column: span.get_utf8_column(),
+            snippet: nth_line(source, span.location_line()),
+            message,
+        }
+    }
+
+    fn at_eof(filename: &str, source: &str, message: &str) -> Self {
+        let line = source.lines().count().max(1) as u32;
+        let snippet = nth_line(source, line);
+
+        ParseError {
+            filename: filename.to_string(),
+            line,
+            column: snippet.chars().count() + 1,
+            snippet,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.filename, self.line, self.column, self.message
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+fn describe(kind: &VerboseErrorKind) -> String {
+    match kind {
+        VerboseErrorKind::Context(ctx) => format!("expected {}", ctx),
+        VerboseErrorKind::Char(c) => format!("expected {:?}", c),
+        VerboseErrorKind::Nom(ErrorKind::Eof) => "expected end of input".to_string(),
+        VerboseErrorKind::Nom(kind) => format!("unexpected input ({})", kind.description()),
+    }
+}
+
+fn nth_line(source: &str, line: u32) -> String {
+    source
+        .lines()
+        .nth(line.saturating_sub(1) as usize)
+        .unwrap_or_default()
+        .to_string()
+}
@@ -1,9 +1,28 @@
 mod ast;
+pub mod cst;
+mod error;
+pub mod owned;
 mod parsers;
+mod printer;
 
 mod scanners;
 #[cfg(test)]
 mod tests;
 
+pub use ast::{
+    parse_authority, BodyPart, Comment, Directive, DirectiveKind, Header, HttpFile, Item,
+    MessageBody, Method, Part, QueryParam, Request, RequestInfo, ResponseRedirect, ResponseRef,
+    ScriptHandler, Segment, Target, TargetError, TargetForm, Template, VariableDeclaration,
+    Version,
+};
+pub use error::ParseError;
 pub use parsers::parse;
+pub use parsers::parse_multiple_request;
 pub use parsers::parse_request;
+pub use parsers::parse_with_recovery;
+pub use parsers::Span;
+
+/// Env file next to the .http files with the variables of each environment.
+pub const PUBLIC_ENV_FILE: &str = "http-client.env.json";
+/// Env file with the secret variables, it is not committed.
+pub const PRIVATE_ENV_FILE: &str = "http-client.private.env.json";
@@ -0,0 +1,400 @@
+//! Owned counterparts of the AST types.
+//!
+//! The AST borrows from the parsed source. Owned nodes copy the text and
+//! keep the position of each span as plain fields, so they can be kept after
+//! the source is dropped or sent to another thread.
+//!
+//! With the `serde` feature the owned nodes implement `Serialize` and
+//! `Deserialize`.
+
+use crate::ast::{self, DirectiveKind as BorrowedDirectiveKind};
+use crate::error::ParseError;
+use crate::parsers;
+use std::fmt;
+use std::time::Duration;
+
+pub use crate::ast::{Method, Version};
+
+/// Text of a span with its position in the source.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub fragment: String,
+    /// 0-based byte offset
+    pub offset: usize,
+    /// 1-based line number
+    pub line: u32,
+    /// 1-based column, counted in characters
+    pub column: usize,
+}
+
+impl<'a> From<parsers::Span<'a>> for Span {
+    fn from(i: parsers::Span<'a>) -> Self {
+        Span {
+            fragment: i.fragment().to_string(),
+            offset: i.location_offset(),
+            line: i.location_line(),
+            column: i.get_utf8_column(),
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.fragment)
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HttpFile {
+    pub filename: String,
+    pub variables: Vec<VariableDeclaration>,
+    pub requests: Vec<Request>,
+    pub comments: Vec<Comment>,
+    pub skipped: Vec<Span>,
+    pub diagnostics: Vec<ParseError>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Request {
+    /// empty span at the first line of the request
+    pub start: Span,
+    pub method: Method,
+    pub line: Span,
+    pub target: Template,
+    pub version: Version,
+    pub headers: Vec<Header>,
+    pub body: MessageBody,
+    pub title: Option<Span>,
+    pub pre_script: ScriptHandler,
+    pub script: ScriptHandler,
+    pub redirect: Option<ResponseRedirect>,
+    pub response_refs: Vec<ResponseRef>,
+    pub comments: Vec<Comment>,
+    pub directives: Vec<Directive>,
+}
+
+impl ast::RequestInfo for Request {
+    fn title(&self) -> Option<&str> {
+        self.title.as_ref().map(|x| x.fragment.as_str())
+    }
+
+    fn start_line(&self) -> u32 {
+        self.start.line
+    }
+
+    fn name_directive(&self) -> Option<&str> {
+        self.directives.iter().find_map(|d| match &d.kind {
+            DirectiveKind::Name(x) => Some(x.fragment.as_str()),
+            _ => None,
+        })
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Header {
+    pub name: Span,
+    pub value: Template,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MessageBody {
+    Bytes(Template),
+    Empty,
+    File(Span),
+    Multipart {
+        span: Span,
+        boundary: Span,
+        parts: Vec<Part>,
+    },
+    Parts {
+        span: Span,
+        parts: Vec<BodyPart>,
+    },
+}
+
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Part {
+    pub headers: Vec<Header>,
+    pub body: MessageBody,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BodyPart {
+    Inline(Template),
+    File(Span),
+}
+
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ScriptHandler {
+    File(Span),
+    Inline(Span),
+    Empty,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ResponseRedirect {
+    pub path: Span,
+    pub overwrite: bool,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ResponseRef {
+    pub path: Span,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Comment {
+    pub span: Span,
+    pub text: Span,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Directive {
+    pub span: Span,
+    pub kind: DirectiveKind,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DirectiveKind {
+    Name(Span),
+    NoRedirect,
+    NoLog,
+    NoCookieJar,
+    Timeout(Duration),
+    ConnectionTimeout(Duration),
+    Other { name: Span, value: Option<Span> },
+}
+
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VariableDeclaration {
+    pub span: Span,
+    pub name: Span,
+    pub value: Template,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Template {
+    pub span: Span,
+    pub segments: Vec<Segment>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Segment {
+    Literal(Span),
+    Variable(Span),
+}
+
+impl Template {
+    pub fn variables(&self) -> impl Iterator<Item = &Span> {
+        self.segments.iter().filter_map(|s| match s {
+            Segment::Variable(name) => Some(name),
+            Segment::Literal(_) => None,
+        })
+    }
+}
+
+// text of the template, placeholders are written as `{{name}}`
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for s in self.segments.iter() {
+            match s {
+                Segment::Literal(x) => f.write_str(&x.fragment)?,
+                Segment::Variable(name) => write!(f, "{{{{{}}}}}", name.fragment)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+////////////////////////
+
+fn spans(i: Vec<parsers::Span>) -> Vec<Span> {
+    i.into_iter().map(Span::from).collect()
+}
+
+impl<'a> ast::HttpFile<'a> {
+    pub fn into_owned(self) -> HttpFile {
+        HttpFile {
+            filename: self.filename.to_string(),
+            variables: self.variables.into_iter().map(|x| x.into_owned()).collect(),
+            requests: self.requests.into_iter().map(|x| x.into_owned()).collect(),
+            comments: self.comments.into_iter().map(|x| x.into_owned()).collect(),
+            skipped: spans(self.skipped),
+            diagnostics: self.diagnostics,
+        }
+    }
+}
+
+impl<'a> ast::Request<'a> {
+    pub fn into_owned(self) -> Request {
+        Request {
+            start: self.start.into(),
+            method: self.method,
+            line: self.line.into(),
+            target: self.target.into_owned(),
+            version: self.version,
+            headers: self.headers.into_iter().map(|x| x.into_owned()).collect(),
+            body: self.body.into_owned(),
+            title: self.title.map(Span::from),
+            pre_script: self.pre_script.into_owned(),
+            script: self.script.into_owned(),
+            redirect: self.redirect.map(|x| x.into_owned()),
+            response_refs: self
+                .response_refs
+                .into_iter()
+                .map(|x| x.into_owned())
+                .collect(),
+            comments: self.comments.into_iter().map(|x| x.into_owned()).collect(),
+            directives: self
+                .directives
+                .into_iter()
+                .map(|x| x.into_owned())
+                .collect(),
+        }
+    }
+}
+
+impl<'a> ast::Header<'a> {
+    pub fn into_owned(self) -> Header {
+        Header {
+            name: self.name.into(),
+            value: self.value.into_owned(),
+        }
+    }
+}
+
+impl<'a> ast::MessageBody<'a> {
+    pub fn into_owned(self) -> MessageBody {
+        match self {
+            ast::MessageBody::Bytes(x) => MessageBody::Bytes(x.into_owned()),
+            ast::MessageBody::Empty => MessageBody::Empty,
+            ast::MessageBody::File(x) => MessageBody::File(x.into()),
+            ast::MessageBody::Multipart {
+                span,
+                boundary,
+                parts,
+            } => MessageBody::Multipart {
+                span: span.into(),
+                boundary: boundary.into(),
+                parts: parts
+                    .into_iter()
+                    .map(|p| Part {
+                        headers: p.headers.into_iter().map(|x| x.into_owned()).collect(),
+                        body: p.body.into_owned(),
+                    })
+                    .collect(),
+            },
+            ast::MessageBody::Parts { span, parts } => MessageBody::Parts {
+                span: span.into(),
+                parts: parts
+                    .into_iter()
+                    .map(|p| match p {
+                        ast::BodyPart::Inline(x) => BodyPart::Inline(x.into_owned()),
+                        ast::BodyPart::File(x) => BodyPart::File(x.into()),
+                    })
+                    .collect(),
+            },
+        }
+    }
+}
+
+impl<'a> ast::ScriptHandler<'a> {
+    pub fn into_owned(self) -> ScriptHandler {
+        match self {
+            ast::ScriptHandler::File(x) => ScriptHandler::File(x.into()),
+            ast::ScriptHandler::Inline(x) => ScriptHandler::Inline(x.into()),
+            ast::ScriptHandler::Empty => ScriptHandler::Empty,
+        }
+    }
+}
+
+impl<'a> ast::ResponseRedirect<'a> {
+    pub fn into_owned(self) -> ResponseRedirect {
+        ResponseRedirect {
+            path: self.path.into(),
+            overwrite: self.overwrite,
+        }
+    }
+}
+
+impl<'a> ast::ResponseRef<'a> {
+    pub fn into_owned(self) -> ResponseRef {
+        ResponseRef {
+            path: self.path.into(),
+        }
+    }
+}
+
+impl<'a> ast::Comment<'a> {
+    pub fn into_owned(self) -> Comment {
+        Comment {
+            span: self.span.into(),
+            text: self.text.into(),
+        }
+    }
+}
+
+impl<'a> ast::Directive<'a> {
+    pub fn into_owned(self) -> Directive {
+        let kind = match self.kind {
+            BorrowedDirectiveKind::Name(x) => DirectiveKind::Name(x.into()),
+            BorrowedDirectiveKind::NoRedirect => DirectiveKind::NoRedirect,
+            BorrowedDirectiveKind::NoLog => DirectiveKind::NoLog,
+            BorrowedDirectiveKind::NoCookieJar => DirectiveKind::NoCookieJar,
+            BorrowedDirectiveKind::Timeout(x) => DirectiveKind::Timeout(x),
+            BorrowedDirectiveKind::ConnectionTimeout(x) => DirectiveKind::ConnectionTimeout(x),
+            BorrowedDirectiveKind::Other { name, value } => DirectiveKind::Other {
+                name: name.into(),
+                value: value.map(Span::from),
+            },
+        };
+
+        Directive {
+            span: self.span.into(),
+            kind,
+        }
+    }
+}
+
+impl<'a> ast::VariableDeclaration<'a> {
+    pub fn into_owned(self) -> VariableDeclaration {
+        VariableDeclaration {
+            span: self.span.into(),
+            name: self.name.into(),
+            value: self.value.into_owned(),
+        }
+    }
+}
+
+impl<'a> ast::Template<'a> {
+    pub fn into_owned(self) -> Template {
+        Template {
+            span: self.span.into(),
+            segments: self
+                .segments
+                .into_iter()
+                .map(|s| match s {
+                    ast::Segment::Literal(x) => Segment::Literal(x.into()),
+                    ast::Segment::Variable(x) => Segment::Variable(x.into()),
+                })
+                .collect(),
+        }
+    }
+}
@@ -1,43 +1,78 @@
-use crate::ast::{Header, MessageBody, Method, Request, ScriptHandler, Version};
+use crate::ast::{
+    BodyPart, Comment, Directive, DirectiveKind, Header, HttpFile, MessageBody, Method, Part,
+    Request, ResponseRedirect, ResponseRef, ScriptHandler, Segment, TargetForm, Template,
+    VariableDeclaration, Version,
+};
+use crate::cst::{self, SyntaxKind, SyntaxNode};
+use crate::error::ParseError;
 use nom::branch::alt;
-use nom::bytes::complete::{tag, take_until, take_while};
+use nom::bytes::complete::{tag, take, take_till, take_until, take_while};
+use nom::character::complete::anychar;
 
-use nom::character::complete::{newline, one_of};
+use nom::character::complete::{line_ending, one_of};
 
-use nom::combinator::{eof, opt, peek, rest};
-use nom::multi::{many0, many_till};
-use nom::sequence::tuple;
+use nom::combinator::{consumed, eof, map, not, opt, recognize, rest};
+use nom::multi::{many0, many1_count, many_till};
+use nom::sequence::{preceded, terminated, tuple};
 
+use nom::Slice;
 use nom_locate::LocatedSpan;
+use std::time::Duration;
 
 use crate::scanners::*;
-use nom::error::context;
+use nom::error::{context, VerboseError, VerboseErrorKind};
 
 pub type Span<'a> = LocatedSpan<&'a str, &'a str>;
 
-pub type IResult<'a, O> = nom::IResult<Span<'a>, O>;
+pub type IResult<'a, O> = nom::IResult<Span<'a>, O, VerboseError<Span<'a>>>;
+
+/// request derived from a `Request` node with the variables declared in it,
+/// or the error of a `Skipped` node
+pub(crate) type Lowered<'a> =
+    Result<(Request<'a>, Vec<VariableDeclaration<'a>>), nom::Err<VerboseError<Span<'a>>>>;
 
 #[derive(PartialEq, Debug)]
 pub struct RequestLine<'a> {
     pub method: Span<'a>,
+    /// whole target including indented continuation lines
     pub target: Span<'a>,
+    /// parts of the target on each line without indentation
+    pub target_fragments: Vec<Span<'a>>,
     pub version: Version,
 }
 
 pub fn parse_request_title(i: Span) -> IResult<Option<Span>> {
     let (i, title) = context("request title", opt(request_title))(i)?;
 
-    return Ok((i, title));
+    Ok((i, title))
 }
 
 pub(crate) fn request_line(i: Span) -> IResult<RequestLine> {
     // [method required-whitespace] request-target [required-whitespace http-version]
-    let (i, method) = token(i)?;
+    // a line starting with `#` is a title or a comment, never a method
+    let (i, method) = context("method", preceded(not(tag("#")), token))(i)?;
     let (i, _) = sp(i)?;
-    let (i, target) = vchar_1(i)?; // TODO: handle all valid urls, read rfc
+    // target form is checked against the method, see RFC 9112 section 3.2
+    let (i, (target, (first, rest))) = context(
+        "request target",
+        consumed(tuple((vchar_1, many0(target_continuation)))),
+    )(i)?;
+    let mut target_fragments = vec![first];
+    target_fragments.extend(rest);
+
+    let text: String = target_fragments.iter().map(|x| *x.fragment()).collect();
+    if TargetForm::detect(&Method::from(method), &text).is_none() {
+        return Err(nom::Err::Error(VerboseError {
+            errors: vec![(
+                target,
+                VerboseErrorKind::Context("request target valid for the method"),
+            )],
+        }));
+    }
     let (i, _) = take_while(is_space_char)(i)?;
-    let (i, version) = http_version(i)?;
-    let (i, _) = opt(tag(NEW_LINE))(i)?;
+    let (i, version) = context("http version", http_version)(i)?;
+    let (i, _) = take_while(is_space_char)(i)?;
+    let (i, _) = context("end of request line", alt((line_ending, eof)))(i)?;
 
     Ok((
         i,
@@ -45,17 +80,32 @@ pub(crate) fn request_line(i: Span) -> IResult<RequestLine> {
             method,
             version,
             target,
+            target_fragments,
         },
     ))
 }
 
 fn http_version(i: Span) -> IResult<Version> {
-    let (i, t) = opt(tag("HTTP/1."))(i)?;
+    let (i, t) = opt(tag("HTTP/"))(i)?;
     if t.is_none() {
         return Ok((i, Version::V11));
     }
 
-    let (i, minor) = one_of("01")(i)?;
+    let (i, major) = one_of("123")(i)?;
+    if major != '1' {
+        // `HTTP/2` and `HTTP/2.0` are both used
+        let (i, _) = opt(tag(".0"))(i)?;
+        return Ok((
+            i,
+            if major == '2' {
+                Version::V2
+            } else {
+                Version::V3
+            },
+        ));
+    }
+
+    let (i, (_, minor)) = tuple((tag("."), one_of("01")))(i)?;
 
     Ok((
         i,
@@ -69,46 +119,253 @@ fn http_version(i: Span) -> IResult<Version> {
 
 // parse one header
 pub(crate) fn parse_header(i: Span) -> IResult<Header> {
-    // let (i, _) = many0(newline)(i)?; // extra newline between header is invalid
+    // let (i, _) = many0(line_ending)(i)?; // extra newline between header is invalid
     let (i, (name, value)) = header(i)?;
+    let (_, value) = parse_template(value)?;
     Ok((i, Header { name, value }))
 }
 
-// parse multiple headers
 pub(crate) fn parse_headers(i: Span) -> IResult<Vec<Header>> {
     let (i, headers) = many0(parse_header)(i)?;
     Ok((i, headers))
 }
 
-// consume content until script, new request or eof
-pub(crate) fn parse_request_body(i: Span) -> IResult<MessageBody> {
-    let (_, until_script) = peek(alt((take_until("> "), rest)))(i)?;
-    let (_, until_title) = peek(alt((take_until("###"), rest)))(i)?;
-
-    // We should consume until to [script start | new title | eof].
-    // alt runs first parser until get error.
-    // if first parser not return error it returns parsed from first parser.
-    // I could not find good way to consume until one of [script start | next title | eof]
-    // TODO: refactor this logic
-    let mut body = Span::new_extra("", "");
-    let mut j = Span::new_extra("", "");
-
-    if until_script.fragment().len() > until_title.fragment().len() {
-        (j, body) = alt((until_new_request_title, rest))(i)?;
+pub(crate) fn parse_comment(i: Span) -> IResult<Comment> {
+    let (i, (span, text)) = comment(i)?;
+    Ok((i, Comment { span, text }))
+}
+
+// directive given in a comment like `# @timeout 10 s`.
+// returns None if the comment is not a directive.
+pub(crate) fn parse_directive<'a>(c: &Comment<'a>) -> Option<Directive<'a>> {
+    let (_, (name, value)) = directive(c.text).ok()?;
+    let value = if value.trim().is_empty() {
+        None
     } else {
-        (j, body) = alt((until_script_start, rest))(i)?;
+        Some(value)
+    };
+
+    let kind = match (*name.fragment(), value) {
+        ("name", Some(x)) => Some(DirectiveKind::Name(x)),
+        ("no-redirect", None) => Some(DirectiveKind::NoRedirect),
+        ("no-log", None) => Some(DirectiveKind::NoLog),
+        ("no-cookie-jar", None) => Some(DirectiveKind::NoCookieJar),
+        ("timeout", Some(x)) => parse_duration(x.fragment()).map(DirectiveKind::Timeout),
+        ("connection-timeout", Some(x)) => {
+            parse_duration(x.fragment()).map(DirectiveKind::ConnectionTimeout)
+        }
+        _ => None,
+    };
+
+    Some(Directive {
+        span: c.span,
+        kind: kind.unwrap_or(DirectiveKind::Other { name, value }),
+    })
+}
+
+// `30`, `30 s`, `500ms` or `2 m`. value without unit is in seconds.
+fn parse_duration(i: &str) -> Option<Duration> {
+    let i = i.trim();
+    let unit_start = i.find(|c: char| !c.is_ascii_digit()).unwrap_or(i.len());
+    let value: u64 = i[..unit_start].parse().ok()?;
+
+    match i[unit_start..].trim() {
+        "" | "s" => Some(Duration::from_secs(value)),
+        "ms" => Some(Duration::from_millis(value)),
+        "m" => Some(Duration::from_secs(value.checked_mul(60)?)),
+        _ => None,
     }
+}
+
+pub(crate) fn parse_variable_declaration(i: Span) -> IResult<VariableDeclaration> {
+    let (i, (span, (name, value))) = consumed(variable_declaration)(i)?;
+    let value = value.slice(..value.fragment().trim_end().len());
+    let (_, value) = parse_template(value)?;
+    let span = span.slice(..span.fragment().trim_end_matches(['\r', '\n']).len());
+    Ok((i, VariableDeclaration { span, name, value }))
+}
+
+// length of the body at the start of the input
+pub(crate) fn body_len(i: Span) -> usize {
+    // body ends at the first of
+    // [script start | response reference | response redirect | new title | eof]
+    let ends: [fn(Span) -> IResult<Span>; 4] = [
+        until_script_start,
+        until_response_ref,
+        until_response_redirect,
+        until_new_request_title,
+    ];
+    ends.iter()
+        .filter_map(|f| f(i).ok())
+        .map(|(_, x)| x.fragment().len())
+        .min()
+        .unwrap_or(i.fragment().len())
+}
+
+// consume content until script, new request or eof
+pub(crate) fn parse_request_body(i: Span) -> IResult<MessageBody> {
+    let (j, body) = take(body_len(i))(i)?;
 
     // clean new lines from beginning of body
-    let (body, _) = many0(newline)(body)?;
+    let (body, _) = many0(line_ending)(body)?;
+
+    let (_, body) = parse_body_content(body)?;
+    Ok((j, body))
+}
 
+// content of a request body or a multipart part
+fn parse_body_content(body: Span) -> IResult<MessageBody> {
     if body.is_empty() {
-        Ok((j, MessageBody::Empty))
-    } else {
-        Ok((j, MessageBody::Bytes(body)))
+        return Ok((body, MessageBody::Empty));
+    }
+
+    // body is only a file reference
+    if let Ok((k, file)) = parse_input_file_ref(body) {
+        if k.fragment().trim().is_empty() {
+            return Ok((k, file));
+        }
+    }
+
+    let (i, parts) = parse_body_parts(body)?;
+    if !parts.iter().any(|x| matches!(x, BodyPart::File(_))) {
+        let (_, body) = parse_template(body)?;
+        return Ok((i, MessageBody::Bytes(body)));
+    }
+
+    Ok((i, MessageBody::Parts { span: body, parts }))
+}
+
+// split the body into inline text and `< path` lines. line endings around the
+// file references are kept in the inline parts.
+fn parse_body_parts(i: Span) -> IResult<Vec<BodyPart>> {
+    let mut parts = vec![];
+    let mut inline: Option<Span> = None;
+    let mut i = i;
+
+    while !i.is_empty() {
+        if let Ok((j, MessageBody::File(path))) = parse_input_file_ref(i) {
+            if let Some(start) = inline.take() {
+                let text = start.slice(..i.location_offset() - start.location_offset());
+                parts.push(BodyPart::Inline(parse_template(text)?.1));
+            }
+            parts.push(BodyPart::File(path));
+            i = j;
+            continue;
+        }
+
+        let (j, _) = recognize(tuple((take_till(|c| c == '\n'), opt(line_ending))))(i)?;
+        inline.get_or_insert(i);
+        i = j;
+    }
+
+    if let Some(start) = inline {
+        parts.push(BodyPart::Inline(parse_template(start)?.1));
+    }
+
+    Ok((i, parts))
+}
+
+// boundary parameter of a `multipart/*` Content-Type header, e.g.
+// `Content-Type: multipart/form-data; boundary="abc"`
+fn multipart_boundary<'a>(headers: &[Header<'a>]) -> Option<Span<'a>> {
+    let value = headers
+        .iter()
+        .find(|h| h.name.fragment().eq_ignore_ascii_case("Content-Type"))?
+        .value
+        .span;
+    if !value
+        .fragment()
+        .to_ascii_lowercase()
+        .starts_with("multipart/")
+    {
+        return None;
+    }
+
+    let start = value.fragment().find("boundary=")? + "boundary=".len();
+    let len = value.fragment()[start..]
+        .find(|c: char| c == ';' || c.is_whitespace())
+        .unwrap_or(value.fragment().len() - start);
+    let boundary = value.slice(start..start + len);
+
+    let boundary = match boundary.fragment().len() {
+        n if n > 2 && boundary.starts_with('"') && boundary.ends_with('"') => {
+            boundary.slice(1..n - 1)
+        }
+        _ => boundary,
+    };
+
+    // boundary is not known until the variables are resolved
+    if boundary.is_empty() || boundary.contains("{{") {
+        return None;
+    }
+
+    Some(boundary)
+}
+
+// split the body on `--boundary` lines. preamble and epilogue are ignored.
+pub(crate) fn parse_multipart<'a>(i: Span<'a>, boundary: &str) -> IResult<'a, Vec<Part<'a>>> {
+    let delimiter = format!("--{}", boundary);
+    // the line ending before a delimiter belongs to the delimiter
+    let next_delimiter = format!("\n{}", delimiter);
+
+    let (mut i, _) = context(
+        "multipart boundary",
+        tuple((take_until(delimiter.as_str()), tag(delimiter.as_str()))),
+    )(i)?;
+
+    let mut parts = vec![];
+    loop {
+        // close delimiter
+        if let Ok((j, _)) = tag::<_, _, VerboseError<Span>>("--")(i) {
+            let (j, _) = rest(j)?;
+            return Ok((j, parts));
+        }
+
+        let (j, _) = tuple((take_while(is_space_char), line_ending))(i)?;
+        let (j, headers) = parse_headers(j)?;
+        let (j, _) = context("empty line after part headers", line_ending)(j)?;
+        let (j, content) = context("multipart boundary", take_until(next_delimiter.as_str()))(j)?;
+        let (j, _) = tag(next_delimiter.as_str())(j)?;
+        let content = match content.fragment().ends_with('\r') {
+            true => content.slice(..content.fragment().len() - 1),
+            false => content,
+        };
+
+        let (_, body) = parse_body_content(content)?;
+        parts.push(Part { headers, body });
+        i = j;
     }
 }
 
+// target can be written on multiple lines, segments of all lines make up the target
+fn target_template<'a>(line: &RequestLine<'a>) -> Template<'a> {
+    Template {
+        span: line.target,
+        segments: line
+            .target_fragments
+            .iter()
+            .flat_map(|x| Template::from(*x).segments)
+            .collect(),
+    }
+}
+
+// split text into literal and `{{variable}}` segments
+pub(crate) fn parse_template(i: Span) -> IResult<Template> {
+    // literal consumes until the next `{{`, so a `{{` that is not a valid
+    // placeholder becomes part of the literal
+    let literal = recognize(many1_count(preceded(
+        not(placeholder),
+        tuple((anychar, alt((take_until("{{"), rest)))),
+    )));
+
+    let (j, segments) = many0(alt((
+        map(placeholder, Segment::Variable),
+        map(literal, Segment::Literal),
+    )))(i)?;
+
+    Ok((j, Template { span: i, segments }))
+}
+
 // parse input file ref that will use for body
 pub(crate) fn parse_input_file_ref(i: Span) -> IResult<MessageBody> {
     let (i, (_, _, file_path)) =
@@ -136,48 +393,178 @@ pub(crate) fn parse_inline_script(i: Span) -> IResult<ScriptHandler> {
 
 pub(crate) fn parse_external_script(i: Span) -> IResult<ScriptHandler> {
     // ‘>’ required-whitespace file-path
-    let (i, (_, path)) = tuple((tag("> "), alt((take_until(NEW_LINE), rest))))(i)?;
+    let (i, (_, path)) = tuple((tag("> "), take_till(|c| c == '\n' || c == '\r')))(i)?;
 
-    return Ok((i, ScriptHandler::File(path)));
+    Ok((i, ScriptHandler::File(path)))
+}
+
+// `< {% script %}` or `< path` before the request line
+pub(crate) fn parse_pre_request_script(i: Span) -> IResult<ScriptHandler> {
+    alt((
+        map(pre_request_script, ScriptHandler::Inline),
+        map(
+            terminated(
+                preceded(tag("< "), take_till(|c| c == '\n' || c == '\r')),
+                many0(line_ending),
+            ),
+            ScriptHandler::File,
+        ),
+        |i| Ok((i, ScriptHandler::Empty)),
+    ))(i)
+}
+
+// `<> path` lines that link saved responses
+pub(crate) fn parse_response_refs(i: Span) -> IResult<Vec<ResponseRef>> {
+    many0(map(terminated(response_ref, many0(line_ending)), |path| {
+        ResponseRef { path }
+    }))(i)
+}
+
+// `>> path` or `>>! path` after the request
+pub(crate) fn parse_response_redirect(i: Span) -> IResult<ResponseRedirect> {
+    let (i, (overwrite, path)) = terminated(response_redirect, many0(line_ending))(i)?;
+    Ok((i, ResponseRedirect { path, overwrite }))
 }
 
 pub fn parse_request(i: Span) -> IResult<Request> {
-    let (i, title) = parse_request_title(i)?;
-    let (i, _) = many0(newline)(i)?;
-    let (i, line) = request_line(i)?;
-    let (i, _) = opt(newline)(i)?; // allowing to only 1 newline
-    let (i, headers) = parse_headers(i)?;
-    let (i, _) = many0(newline)(i)?;
-    let (i, body) = parse_request_body(i)?;
-    let (i, script) = parse_script(i)?;
-    let (i, _) = many0(newline)(i)?;
+    let (i, nodes) = cst::request(i)?;
+    let (request, _) = lower_request(&nodes[nodes.len() - 1])?;
+    Ok((i, request))
+}
 
-    Ok((
-        i,
-        Request {
-            method: Method::from(line.method),
-            target: line.target.fragment().to_string(),
-            version: line.version,
-            title,
-            headers,
-            body,
-            script,
-        },
-    ))
+// request of a request node with the variables declared in it
+pub(crate) fn lower_request<'a>(node: &SyntaxNode<'a>) -> Lowered<'a> {
+    let mut title = None;
+    let mut comments = vec![];
+    let mut directives = vec![];
+    let mut variables = vec![];
+    let mut pre_script = ScriptHandler::Empty;
+    let mut line = None;
+    let mut headers = vec![];
+    let mut body = MessageBody::Empty;
+    let mut response_refs = vec![];
+    let mut redirect = None;
+    let mut script = ScriptHandler::Empty;
+
+    for x in node.children.iter() {
+        let i = x.span;
+        match x.kind {
+            SyntaxKind::Title => title = parse_request_title(i)?.1,
+            SyntaxKind::Comment => comments.push(parse_comment(i)?.1),
+            // comments before the request line can be directives
+            SyntaxKind::Directive => directives.extend(parse_directive(&parse_comment(i)?.1)),
+            SyntaxKind::Variable => variables.push(parse_variable_declaration(i)?.1),
+            SyntaxKind::PreScript => pre_script = parse_pre_request_script(i)?.1,
+            SyntaxKind::RequestLine => {
+                let span = i.slice(..i.fragment().trim_end_matches(['\r', '\n']).len());
+                line = Some((span, request_line(i)?.1));
+            }
+            SyntaxKind::Header => headers.push(parse_header(i)?.1),
+            SyntaxKind::Body => body = parse_request_body(i)?.1,
+            SyntaxKind::ResponseRef => response_refs.extend(parse_response_refs(i)?.1),
+            SyntaxKind::Redirect => redirect = Some(parse_response_redirect(i)?.1),
+            SyntaxKind::Script => script = parse_script(i)?.1,
+            _ => {}
+        }
+    }
+
+    let Some((line_span, line)) = line else {
+        return Err(nom::Err::Error(VerboseError {
+            errors: vec![(node.span, VerboseErrorKind::Context("request line"))],
+        }));
+    };
+    let body = match (multipart_boundary(&headers), body) {
+        (Some(boundary), MessageBody::Bytes(Template { span, .. }))
+        | (Some(boundary), MessageBody::Parts { span, .. }) => {
+            let (_, parts) = parse_multipart(span, boundary.fragment())?;
+            MessageBody::Multipart {
+                span,
+                boundary,
+                parts,
+            }
+        }
+        (_, body) => body,
+    };
+
+    let request = Request {
+        start: node.span.slice(..0),
+        method: Method::from(line.method),
+        line: line_span,
+        target: target_template(&line),
+        version: line.version,
+        title,
+        headers,
+        body,
+        pre_script,
+        script,
+        redirect,
+        response_refs,
+        comments,
+        directives,
+    };
+    Ok((request, variables))
 }
 
+// requests until the end of the input, fails on the first broken request
 pub fn parse_multiple_request(i: Span) -> IResult<Vec<Request>> {
     let (i, (requests, _)) = many_till(parse_request, eof)(i)?;
     Ok((i, requests))
-    // we can split content at here and give each part of the span as separate
-    // !peek(parse_request_title)(i).is_ok() && !peek(empty_lines)(i).is_ok()
 }
 
-pub fn parse<'a>(filename: &'a str, i: &'a str) -> Vec<Request<'a>> {
-    // TODO: error handling
-    let (_, requests) = parse_multiple_request(Span::new_extra(i, filename))
-        .map_err(|e| format!("request parse failed: {:?}", e))
-        .unwrap();
+// parse the file, fails on the first broken request. only the error of the
+// first broken request is returned, use parse_with_recovery to get all errors.
+pub fn parse<'a>(filename: &'a str, source: &'a str) -> Result<HttpFile<'a>, ParseError> {
+    let mut file = parse_with_recovery(filename, source);
+    match file.diagnostics.is_empty() {
+        true => Ok(file),
+        false => Err(file.diagnostics.remove(0)),
+    }
+}
+
+// parse all well-formed requests and collect the errors of the broken ones
+// in the diagnostics of the file. a broken request is skipped until the next
+// `###` title.
+pub fn parse_with_recovery<'a>(filename: &'a str, source: &'a str) -> HttpFile<'a> {
+    let (tree, lowered) = cst::file(Span::new_extra(source, filename));
+    lower_file(tree, lowered)
+}
+
+// file of the top-level nodes of the syntax tree, `lowered` has the result
+// of each `Request` and `Skipped` node in order
+pub(crate) fn lower_file<'a>(mut tree: SyntaxNode<'a>, lowered: Vec<Lowered<'a>>) -> HttpFile<'a> {
+    let (filename, source) = (tree.span.extra, *tree.span.fragment());
+    let nodes = std::mem::take(&mut tree.children);
+    let mut lowered = lowered.into_iter();
+    let mut file = HttpFile::new(tree);
+
+    // the tree is built by the same parsers, so its lines parse again
+    for node in nodes.iter() {
+        match node.kind {
+            SyntaxKind::Variable => {
+                if let Ok((_, x)) = parse_variable_declaration(node.span) {
+                    file.push_variable(x);
+                }
+            }
+            SyntaxKind::Comment | SyntaxKind::Directive => {
+                if let Ok((_, x)) = parse_comment(node.span) {
+                    file.push_comment(x);
+                }
+            }
+            SyntaxKind::Request | SyntaxKind::Skipped => match lowered.next() {
+                Some(Ok((request, variables))) => {
+                    // variables declared inside of the request are set before it runs
+                    variables.into_iter().for_each(|x| file.push_variable(x));
+                    file.push_request(request);
+                }
+                Some(Err(e)) => {
+                    file.push_skipped(node.span, ParseError::from_nom(filename, source, e))
+                }
+                None => {}
+            },
+            _ => {}
+        }
+    }
 
-    requests
+    file.tree.children = nodes;
+    file
 }
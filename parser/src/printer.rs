@@ -0,0 +1,247 @@
+//! Canonical .http text of the AST.
+//!
+//! Header spacing, blank lines and `###` separators are normalized. Comments,
+//! bodies and scripts are written as they are in the source, only the line
+//! endings after a body are trimmed.
+
+use crate::ast::{HttpFile, MessageBody, Request, ScriptHandler, VariableDeclaration};
+use crate::parsers::Span;
+use crate::scanners::NEW_LINE;
+use std::fmt::{self, Write};
+
+// a line or a block of the file, ordered by its position in the source
+enum Entry<'f, 'a> {
+    Title(&'f Request<'a>),
+    Comment(Span<'a>),
+    Variable(&'f VariableDeclaration<'a>),
+    PreScript(&'f ScriptHandler<'a>),
+    /// request line and everything after it
+    Request(&'f Request<'a>),
+    Skipped(Span<'a>),
+}
+
+impl<'f, 'a> Entry<'f, 'a> {
+    fn offset(&self) -> usize {
+        match self {
+            Entry::Title(r) => r.title.map_or(0, |x| x.location_offset()),
+            Entry::Comment(x) | Entry::Skipped(x) => x.location_offset(),
+            Entry::Variable(x) => x.span.location_offset(),
+            Entry::PreScript(ScriptHandler::Inline(x) | ScriptHandler::File(x)) => {
+                x.location_offset()
+            }
+            Entry::PreScript(ScriptHandler::Empty) => 0,
+            Entry::Request(r) => r.line.location_offset(),
+        }
+    }
+}
+
+// lines of the request in source order, the lines before the request line
+// start the request. variables of the file declared in the request are
+// written in place.
+fn request_entries<'f, 'a>(
+    r: &'f Request<'a>,
+    variables: &'f [VariableDeclaration<'a>],
+) -> Vec<Entry<'f, 'a>> {
+    let line = r.line.location_offset();
+    let mut entries = vec![Entry::Request(r)];
+    if r.title.is_some() {
+        entries.push(Entry::Title(r));
+    }
+    entries.extend(
+        r.comments
+            .iter()
+            .filter(|c| c.span.location_offset() < line)
+            .map(|c| Entry::Comment(c.span)),
+    );
+    entries.extend(r.directives.iter().map(|d| Entry::Comment(d.span)));
+    if r.pre_script != ScriptHandler::Empty {
+        entries.push(Entry::PreScript(&r.pre_script));
+    }
+    entries.extend(
+        variables
+            .iter()
+            .filter(|x| (r.start_offset()..line).contains(&x.span.location_offset()))
+            .map(Entry::Variable),
+    );
+    entries.sort_by_key(Entry::offset);
+
+    entries
+}
+
+// whether the lines after the request would be read as its body
+fn reads_body(r: &Request) -> bool {
+    r.script == ScriptHandler::Empty && r.redirect.is_none() && r.response_refs.is_empty()
+}
+
+// groups of entries in source order, a group is a request with its leading
+// lines or a single entry outside of the requests
+fn write_groups(f: &mut fmt::Formatter<'_>, mut groups: Vec<Vec<Entry>>) -> fmt::Result {
+    groups.sort_by_key(|x| x.first().map_or(0, Entry::offset));
+
+    let requests_after: Vec<bool> = (0..groups.len())
+        .map(|i| groups[i + 1..].iter().any(|g| request_of(g).is_some()))
+        .collect();
+    let mut previous: Option<&Request> = None;
+    for (i, group) in groups.iter().enumerate() {
+        let titled = group.iter().any(|e| match e {
+            Entry::Title(_) => true,
+            Entry::Skipped(x) => x.fragment().starts_with("###"),
+            _ => false,
+        });
+        match previous {
+            // a separator would be read as the request line of a following request
+            Some(r) if !titled && (reads_body(r) || !requests_after[i]) => {
+                write!(f, "{}###{}", NEW_LINE, NEW_LINE)?
+            }
+            Some(_) => f.write_str(NEW_LINE)?,
+            None if i > 0 && (titled || request_of(group).is_some()) => f.write_str(NEW_LINE)?,
+            None => {}
+        }
+
+        for e in group.iter() {
+            write_entry(f, e)?;
+        }
+        previous = request_of(group);
+    }
+
+    Ok(())
+}
+
+fn request_of<'f, 'a>(group: &[Entry<'f, 'a>]) -> Option<&'f Request<'a>> {
+    group.iter().find_map(|e| match e {
+        Entry::Request(r) => Some(*r),
+        _ => None,
+    })
+}
+
+fn write_entry(f: &mut fmt::Formatter<'_>, e: &Entry) -> fmt::Result {
+    match e {
+        Entry::Title(r) => {
+            let title = r.title.map_or("", |x| x.fragment().trim());
+            match title.is_empty() {
+                true => f.write_str("###")?,
+                false => write!(f, "### {}", title)?,
+            }
+            f.write_str(NEW_LINE)
+        }
+        Entry::Comment(x) => write!(f, "{}{}", x.fragment(), NEW_LINE),
+        Entry::Variable(x) => write!(
+            f,
+            "@{} = {}{}",
+            x.name.fragment(),
+            x.value.span.fragment().trim_end(),
+            NEW_LINE
+        ),
+        Entry::PreScript(x) => write!(f, "{}", Script("<", x)),
+        Entry::Request(r) => write_request(f, r),
+        Entry::Skipped(x) => write!(f, "{}{}", trim_line_endings(x.fragment()), NEW_LINE),
+    }
+}
+
+// request line, headers, body and the lines after the body
+fn write_request(f: &mut fmt::Formatter<'_>, r: &Request) -> fmt::Result {
+    write!(f, "{} {}", r.method.as_str(), r.target.span.fragment())?;
+    if r.has_version() {
+        write!(f, " {}", r.version.as_str())?;
+    }
+    f.write_str(NEW_LINE)?;
+
+    // comments between the headers are kept in place
+    let line = r.line.location_offset();
+    let mut lines: Vec<(usize, String)> = r
+        .headers
+        .iter()
+        .map(|h| {
+            let text = format!(
+                "{}: {}",
+                h.name.fragment(),
+                h.value.span.fragment().trim_end()
+            );
+            (h.name.location_offset(), text)
+        })
+        .collect();
+    lines.extend(
+        r.comments
+            .iter()
+            .filter(|c| c.span.location_offset() > line)
+            .map(|c| (c.span.location_offset(), c.span.fragment().to_string())),
+    );
+    lines.sort_by_key(|x| x.0);
+    for (_, text) in lines.iter() {
+        write!(f, "{}{}", text, NEW_LINE)?;
+    }
+
+    let body = match &r.body {
+        MessageBody::Empty => None,
+        MessageBody::File(path) => Some(format!("< {}", path.fragment())),
+        x => x
+            .get_span()
+            .map(|x| trim_line_endings(x.fragment()).to_string()),
+    };
+    if let Some(body) = body {
+        write!(f, "{}{}{}", NEW_LINE, body, NEW_LINE)?;
+    }
+
+    let mut after = String::new();
+    if r.script != ScriptHandler::Empty {
+        write!(after, "{}", Script(">", &r.script))?;
+    }
+    if let Some(x) = &r.redirect {
+        let marker = if x.overwrite { ">>!" } else { ">>" };
+        write!(after, "{} {}{}", marker, x.path.fragment(), NEW_LINE)?;
+    }
+    for x in r.response_refs.iter() {
+        write!(after, "<> {}{}", x.path.fragment(), NEW_LINE)?;
+    }
+    if !after.is_empty() {
+        write!(f, "{}{}", NEW_LINE, after)?;
+    }
+
+    Ok(())
+}
+
+// `> {% script %}` or `> path` line, marker is `<` for pre-request scripts
+struct Script<'f, 'a>(&'static str, &'f ScriptHandler<'a>);
+
+impl<'f, 'a> fmt::Display for Script<'f, 'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.1 {
+            ScriptHandler::Inline(x) => write!(f, "{} {{%{}%}}{}", self.0, x.fragment(), NEW_LINE),
+            ScriptHandler::File(x) => write!(f, "{} {}{}", self.0, x.fragment(), NEW_LINE),
+            ScriptHandler::Empty => Ok(()),
+        }
+    }
+}
+
+fn trim_line_endings(i: &str) -> &str {
+    i.trim_end_matches(['\r', '\n'])
+}
+
+impl<'a> fmt::Display for Request<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_groups(f, vec![request_entries(self, &[])])
+    }
+}
+
+impl<'a> fmt::Display for HttpFile<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut groups: Vec<Vec<Entry>> = self
+            .requests
+            .iter()
+            .map(|r| request_entries(r, &self.variables))
+            .collect();
+        // variables in a request are written with it
+        let outside = self.variables.iter().filter(|x| {
+            let offset = x.span.location_offset();
+            !self
+                .requests
+                .iter()
+                .any(|r| (r.start_offset()..r.line.location_offset()).contains(&offset))
+        });
+        groups.extend(outside.map(|x| vec![Entry::Variable(x)]));
+        groups.extend(self.comments.iter().map(|c| vec![Entry::Comment(c.span)]));
+        groups.extend(self.skipped.iter().map(|x| vec![Entry::Skipped(*x)]));
+
+        write_groups(f, groups)
+    }
+}
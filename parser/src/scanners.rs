@@ -1,39 +1,101 @@
 use crate::parsers::{IResult, Span};
 use nom::branch::alt;
-use nom::bytes::complete::{tag, take_until, take_until1, take_while};
-use nom::character::complete::char;
+use nom::bytes::complete::{
+    tag, take, take_till, take_until, take_until1, take_while, take_while1,
+};
+use nom::character::complete::{char, line_ending, not_line_ending};
 use nom::character::is_alphanumeric;
-use nom::combinator::eof;
-use nom::multi::many0;
-use nom::sequence::tuple;
+use nom::combinator::{consumed, eof, not, opt, recognize, rest};
+use nom::error::{ErrorKind, ParseError, VerboseError};
+use nom::multi::{many0, many1_count};
+use nom::sequence::{preceded, tuple};
 
-#[cfg(all(not(target_os = "windows")))]
+#[cfg(not(target_os = "windows"))]
 pub(crate) const NEW_LINE: &str = "\n";
 
 #[cfg(target_os = "windows")]
-const NEW_LINE: &str = "\r\n";
+pub(crate) const NEW_LINE: &str = "\r\n";
 
 const SCRIPT_START: &str = "> ";
 const SCRIPT_END: &str = "%}";
+const RESPONSE_REF_START: &str = "<> ";
+const REDIRECT_START: &str = ">>";
 
 pub fn request_title(i: Span) -> IResult<Span> {
     let (i, (_, _, _, title, _)) = tuple((
-        many0(tag(NEW_LINE)),
+        many0(line_ending),
         tag("###"),
         many0(tag(" ")),
-        take_until(NEW_LINE),
-        tag(NEW_LINE),
+        not_line_ending,
+        alt((line_ending, eof)),
     ))(i)?;
 
-    return Ok((i, title));
+    Ok((i, title))
 }
 
 pub fn until_new_request_title(i: Span) -> IResult<Span> {
     take_until("###")(i) // TODO: add all line matcher, not only ###
 }
 
+// consume the current line and everything until the next `###` line or eof
+pub fn skip_to_next_request_title(i: Span) -> IResult<Span> {
+    recognize(tuple((
+        take_till(|c| c == '\n'),
+        alt((recognize(tuple((take_until("\n###"), tag("\n")))), rest)),
+    )))(i)
+}
+
 pub fn until_script_start(i: Span) -> IResult<Span> {
-    take_until(SCRIPT_START)(i)
+    // `<> ` of response references and `>> ` of redirects are not a script start
+    let text = i.fragment();
+    match text
+        .match_indices(SCRIPT_START)
+        .find(|(x, _)| !text[..*x].ends_with(['<', '>']))
+    {
+        Some((x, _)) => take(x)(i),
+        None => Err(nom::Err::Error(VerboseError::from_error_kind(
+            i,
+            ErrorKind::TakeUntil,
+        ))),
+    }
+}
+
+// until the next line that starts with `<> `
+pub fn until_response_ref(i: Span) -> IResult<Span> {
+    if i.fragment().starts_with(RESPONSE_REF_START) {
+        return take(0usize)(i);
+    }
+
+    recognize(tuple((take_until("\n<> "), tag("\n"))))(i)
+}
+
+// until the next line that starts with `>>`
+pub fn until_response_redirect(i: Span) -> IResult<Span> {
+    if i.fragment().starts_with(REDIRECT_START) {
+        return take(0usize)(i);
+    }
+
+    recognize(tuple((take_until("\n>>"), tag("\n"))))(i)
+}
+
+// `>> path` or `>>! path`, returns whether the file is overwritten and the path
+pub fn response_redirect(i: Span) -> IResult<(bool, Span)> {
+    let (i, (_, overwrite, _, path)) = tuple((
+        tag(REDIRECT_START),
+        opt(char('!')),
+        many1_count(char(' ')),
+        take_till(|c| c == '\n' || c == '\r'),
+    ))(i)?;
+
+    Ok((i, (overwrite.is_some(), path)))
+}
+
+// `<> path`, returns the path
+pub fn response_ref(i: Span) -> IResult<Span> {
+    preceded(
+        tag(RESPONSE_REF_START),
+        take_till(|c| c == '\n' || c == '\r'),
+    )(i)
 }
 
 pub fn inline_script(i: Span) -> IResult<Span> {
@@ -42,19 +104,91 @@ pub fn inline_script(i: Span) -> IResult<Span> {
         tag("{%"),
         take_until1(SCRIPT_END),
         tag(SCRIPT_END),
-        many0(tag(NEW_LINE)),
+        many0(line_ending),
     ))(i)?;
 
     Ok((i, script))
 }
 
+// `< {% script %}` before the request line
+pub fn pre_request_script(i: Span) -> IResult<Span> {
+    let (i, (_, _, script, _, _)) = tuple((
+        tag("< "),
+        tag("{%"),
+        take_until1(SCRIPT_END),
+        tag(SCRIPT_END),
+        many0(line_ending),
+    ))(i)?;
+
+    Ok((i, script))
+}
+
+// `{{ name }}` or `{{ $name(args) }}`, returns the name with the arguments
+pub fn placeholder(i: Span) -> IResult<Span> {
+    let (i, (_, _, name, _, _)) = tuple((
+        tag("{{"),
+        take_while(is_space_char),
+        recognize(tuple((
+            take_while1(is_variable_char),
+            opt(tuple((
+                char('('),
+                take_while(|c| c != ')' && c != '}' && c != '\n'),
+                char(')'),
+            ))),
+        ))),
+        take_while(is_space_char),
+        tag("}}"),
+    ))(i)?;
+
+    Ok((i, name))
+}
+
+// `# text` or `// text` line, returns the whole comment and the text after the marker
+pub fn comment(i: Span) -> IResult<(Span, Span)> {
+    let (i, _) = not(tag("###"))(i)?;
+    let (i, (whole, text)) = consumed(preceded(
+        tuple((alt((tag("#"), tag("//"))), take_while(is_space_char))),
+        not_line_ending,
+    ))(i)?;
+    let (i, _) = alt((line_ending, eof))(i)?;
+
+    Ok((i, (whole, text)))
+}
+
+// `@name value`, returns name and value
+pub fn directive(i: Span) -> IResult<(Span, Span)> {
+    let (i, (_, name, _, value)) = tuple((
+        tag("@"),
+        take_while1(|c: char| c.is_alphanumeric() || c == '-' || c == '_'),
+        take_while(is_space_char),
+        rest,
+    ))(i)?;
+
+    Ok((i, (name, value)))
+}
+
+// `@name = value` line, returns name and value
+pub fn variable_declaration(i: Span) -> IResult<(Span, Span)> {
+    let (i, (_, name, _, _, _, value, _)) = tuple((
+        tag("@"),
+        take_while1(|c: char| c.is_alphanumeric() || "_-.".contains(c)),
+        take_while(is_space_char),
+        tag("="),
+        take_while(is_space_char),
+        not_line_ending,
+        alt((line_ending, eof)),
+    ))(i)?;
+
+    Ok((i, (name, value)))
+}
+
 pub fn header(i: Span) -> IResult<(Span, Span)> {
     let (i, (name, _, _, value, _)) = tuple((
         token,
         tag(":"),
         take_while(is_space_char),
         take_while(is_header_value_char),
-        tag(NEW_LINE),
+        line_ending,
     ))(i)?;
 
     Ok((i, (name, value)))
@@ -70,21 +204,34 @@ pub fn is_header_value_char(i: char) -> bool {
     */
     let i = i as u32;
 
-    i == 9 || (i >= 32 && i <= 126)
+    i == 9 || (32..=126).contains(&i)
 }
 
 pub fn token(i: Span) -> IResult<Span> {
-    take_while(is_token_char)(i)
+    take_while1(is_token_char)(i)
 }
 
 pub fn vchar_1(i: Span) -> IResult<Span> {
     take_while(is_vchar)(i)
 }
 
+#[allow(dead_code)]
 fn empty_lines(i: Span) -> IResult<Span> {
     alt((tag(NEW_LINE), tag("\n"), tag("\r"), eof))(i)
 }
 
+// indented line that continues the request target, returns the part without indentation
+pub fn target_continuation(i: Span) -> IResult<Span> {
+    let (i, (_, _, _, fragment)) = tuple((
+        take_while(is_space_char),
+        line_ending,
+        take_while1(|c| c == ' ' || c == '\t'),
+        take_while1(is_vchar),
+    ))(i)?;
+
+    Ok((i, fragment))
+}
+
 fn is_token_char(i: char) -> bool {
     is_alphanumeric(i as u8) || "!#$%&'*+-.^_`|~".contains(i)
 }
@@ -94,6 +241,10 @@ fn is_vchar(i: char) -> bool {
     i as u32 > 32 && i as u32 <= 126
 }
 
+fn is_variable_char(i: char) -> bool {
+    i.is_alphanumeric() || "_-.$".contains(i)
+}
+
 pub fn is_space_char(x: char) -> bool {
     x == ' '
 }